@@ -0,0 +1,48 @@
+//! Compares the elfcode interpreter with the compiled fast path on the day21 program.
+//!
+//! Run with `cargo bench --bench vm`.
+
+use aoc2018::vm::compiled::Compiled;
+use aoc2018::vm::{Limits, Vm};
+use std::fs;
+use std::time::{Duration, Instant};
+
+const STEPS: usize = 20_000_000;
+
+fn measure(name: &str, mut run: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    let result = run();
+    let elapsed = start.elapsed();
+    let per_step = elapsed.as_nanos() as f64 / STEPS as f64;
+    println!(
+        "{:<12} {:>10.2?}  {:>6.2} ns/step  (r3 = {})",
+        name, elapsed, per_step, result
+    );
+    elapsed
+}
+
+fn main() {
+    let input = fs::read_to_string("input/2018/day21.txt").unwrap();
+    let mut vm: Vm = input.parse().unwrap();
+    vm.accelerate = false;
+
+    let interpreted = measure("interpreter", || {
+        let mut vm = vm.clone();
+        vm.run_with(Limits::new().max_steps(STEPS)).unwrap();
+        vm.registers.0[3]
+    });
+
+    let compiled = Compiled::<6>::new(&vm).unwrap();
+    let fast = measure("compiled", || {
+        let mut vm = vm.clone();
+        compiled
+            .run_with(&mut vm, Limits::new().max_steps(STEPS))
+            .unwrap();
+        vm.registers.0[3]
+    });
+
+    println!(
+        "speedup      {:.1}x",
+        interpreted.as_secs_f64() / fast.as_secs_f64()
+    );
+}
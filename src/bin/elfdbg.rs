@@ -0,0 +1,169 @@
+use aoc2018::vm::debugger::{Command, Debugger, HELP, HISTORY_SIZE};
+use aoc2018::vm::{disasm, Vm};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn print_registers(debugger: &mut Debugger) {
+    let ip_index = debugger.vm.ip_index();
+    let registers: Vec<String> = debugger
+        .vm
+        .registers
+        .0
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            if i == ip_index {
+                format!("r{}(ip)={}", i, value)
+            } else {
+                format!("r{}={}", i, value)
+            }
+        })
+        .collect();
+    println!("{}", registers.join(" "));
+}
+
+fn print_program(debugger: &mut Debugger) {
+    let ip = debugger.vm.ip().map(|ip| *ip).ok();
+    for line in disasm::disassemble(&debugger.vm).lines {
+        if let Some(label) = &line.label {
+            println!("{}:", label);
+        }
+        let marker = if Some(line.ip) == ip { '>' } else { ' ' };
+        let breakpoint = if debugger.breakpoints().contains(&line.ip) {
+            '*'
+        } else {
+            ' '
+        };
+        let instruction = line.instruction.to_string();
+        println!(
+            "{}{}{:4}  {:<16}  {}",
+            marker, breakpoint, line.ip, instruction, line.statement
+        );
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: elfdbg <program or snapshot> [history steps]");
+            process::exit(2);
+        }
+    };
+    let history = match env::args().nth(2).map(|steps| steps.parse()) {
+        Some(Ok(steps)) => steps,
+        Some(Err(e)) => {
+            eprintln!("history steps: {}", e);
+            process::exit(2);
+        }
+        None => HISTORY_SIZE,
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let vm = if source.starts_with("#snapshot") {
+        Vm::restore(&source)
+    } else {
+        source.parse()
+    };
+    let vm: Vm = vm.unwrap_or_else(|e| {
+        eprintln!("{}:{}", path, e);
+        process::exit(1);
+    });
+
+    let mut debugger = Debugger::with_history(vm, history);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(elfdbg) ");
+        io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command: Command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+
+        let event = match command {
+            Command::Step(steps) => debugger.step(steps),
+            Command::Continue(max_steps) => debugger.resume(max_steps),
+            Command::Until => debugger.step_over_loop(),
+            Command::Back(steps) => debugger.step_back(steps),
+            Command::Rewind(ip) => debugger.rewind_to(ip),
+            Command::Break(ip) => {
+                debugger.add_breakpoint(ip);
+                continue;
+            }
+            Command::Delete(ip) => {
+                if !debugger.remove_breakpoint(ip) {
+                    println!("no breakpoint at ip {}", ip);
+                }
+                continue;
+            }
+            Command::Watch(register) => {
+                if let Err(e) = debugger.add_watchpoint(register) {
+                    println!("{}", e);
+                }
+                continue;
+            }
+            Command::Unwatch(register) => {
+                if !debugger.remove_watchpoint(register) {
+                    println!("no watchpoint on r{}", register);
+                }
+                continue;
+            }
+            Command::Set(register, value) => {
+                if let Err(e) = debugger.set_register(register, value) {
+                    println!("{}", e);
+                }
+                continue;
+            }
+            Command::Jump(ip) => {
+                match debugger.vm.ip() {
+                    Ok(register) => *register = ip,
+                    Err(e) => println!("{}", e),
+                }
+                continue;
+            }
+            Command::Registers => {
+                print_registers(&mut debugger);
+                continue;
+            }
+            Command::List => {
+                print_program(&mut debugger);
+                continue;
+            }
+            Command::Save(path) => {
+                let saved = fs::File::create(&path).and_then(|file| debugger.vm.save(file));
+                if let Err(e) = saved {
+                    println!("{}: {}", path, e);
+                }
+                continue;
+            }
+            Command::Help => {
+                println!("{}", HELP);
+                continue;
+            }
+            Command::Quit => break,
+        };
+
+        match event {
+            Ok(event) => println!("{}", event),
+            Err(fault) => println!("fault: {}", fault),
+        }
+        print_registers(&mut debugger);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::grid::{self, Grid, Point, Tile as _};
 use lazy_static::lazy_static;
 use na::{Matrix2, Vector2};
 use std::cmp::Ordering;
@@ -5,8 +6,6 @@ use std::collections::HashSet;
 use std::fmt;
 use std::str::FromStr;
 
-type Grid = Vec<Vec<Tile>>;
-
 lazy_static! {
     static ref TURN_LEFT: Matrix2<i32> = Matrix2::new(0, 1, -1, 0);
     static ref GO_STRAIGHT: Matrix2<i32> = Matrix2::new(1, 0, 0, 1);
@@ -23,6 +22,31 @@ pub enum Tile {
     Intersection,
 }
 
+impl grid::Tile for Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            ' ' => Some(Tile::None),
+            '-' => Some(Tile::Horizontal),
+            '|' => Some(Tile::Vertical),
+            '\\' => Some(Tile::PositiveSlope),
+            '/' => Some(Tile::NegativeSlope),
+            '+' => Some(Tile::Intersection),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Tile::None => ' ',
+            Tile::Horizontal => '-',
+            Tile::Vertical => '|',
+            Tile::PositiveSlope => '\\',
+            Tile::NegativeSlope => '/',
+            Tile::Intersection => '+',
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Cart {
     position: Vector2<i32>,
@@ -53,11 +77,10 @@ impl Cart {
         }
     }
 
-    #[allow(clippy::ptr_arg)]
-    pub fn tick(&mut self, grid: &Grid) {
+    pub fn tick(&mut self, grid: &Grid<Tile>) {
         self.position += self.direction;
 
-        let tile = &grid[self.position.y as usize][self.position.x as usize];
+        let tile = &grid[Point::from(self.position)];
         match tile {
             Tile::Horizontal | Tile::Vertical => {}
             Tile::PositiveSlope => {
@@ -89,7 +112,7 @@ impl PartialOrd for Cart {
 
 #[derive(Debug, Clone)]
 pub struct System {
-    grid: Grid,
+    grid: Grid<Tile>,
     carts: Vec<Cart>,
 }
 
@@ -149,68 +172,33 @@ impl System {
 }
 
 impl FromStr for System {
-    type Err = ();
+    type Err = grid::ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut carts = Vec::new();
-        let mut grid: Vec<Vec<Tile>> = s
-            .lines()
-            .enumerate()
-            .map(|(y, line)| {
-                line.chars()
-                    .enumerate()
-                    .map(|(x, tile)| match tile {
-                        ' ' => Tile::None,
-                        '-' => Tile::Horizontal,
-                        '|' => Tile::Vertical,
-                        '/' => Tile::NegativeSlope,
-                        '\\' => Tile::PositiveSlope,
-                        '+' => Tile::Intersection,
-                        '^' => {
-                            carts.push(Cart::new(
-                                Vector2::new(x as i32, y as i32),
-                                Vector2::new(0, -1),
-                            ));
-                            Tile::None
-                        }
-                        'v' => {
-                            carts.push(Cart::new(
-                                Vector2::new(x as i32, y as i32),
-                                Vector2::new(0, 1),
-                            ));
-                            Tile::None
-                        }
-                        '<' => {
-                            carts.push(Cart::new(
-                                Vector2::new(x as i32, y as i32),
-                                Vector2::new(-1, 0),
-                            ));
-                            Tile::None
-                        }
-                        '>' => {
-                            carts.push(Cart::new(
-                                Vector2::new(x as i32, y as i32),
-                                Vector2::new(1, 0),
-                            ));
-                            Tile::None
-                        }
-                        _ => unreachable!(),
-                    })
-                    .collect()
-            })
-            .collect();
-
+        let mut grid = Grid::parse_with(s, |p, c| {
+            let direction = match c {
+                '^' => Vector2::new(0, -1),
+                'v' => Vector2::new(0, 1),
+                '<' => Vector2::new(-1, 0),
+                '>' => Vector2::new(1, 0),
+                c => return Tile::from_char(c),
+            };
+            carts.push(Cart::new(p.into(), direction));
+            Some(Tile::None)
+        })?;
+
+        let is_track = |grid: &Grid<Tile>, p| grid.get(p).is_some_and(|t| *t != Tile::None);
         for cart in &carts {
-            if grid[cart.position.y as usize][cart.position.x as usize - 1] != Tile::None
-                && grid[cart.position.y as usize][cart.position.x as usize + 1] != Tile::None
-            {
-                grid[cart.position.y as usize][cart.position.x as usize] = Tile::Horizontal;
-            } else if grid[cart.position.y as usize - 1][cart.position.x as usize] != Tile::None
-                && grid[cart.position.y as usize + 1][cart.position.x as usize] != Tile::None
+            let p = Point::from(cart.position);
+            if is_track(&grid, p + Point::new(-1, 0)) && is_track(&grid, p + Point::new(1, 0)) {
+                grid[p] = Tile::Horizontal;
+            } else if is_track(&grid, p + Point::new(0, -1))
+                && is_track(&grid, p + Point::new(0, 1))
             {
-                grid[cart.position.y as usize][cart.position.x as usize] = Tile::Vertical;
+                grid[p] = Tile::Vertical;
             } else {
-                eprintln!("{:?}", &grid);
+                eprintln!("{}", &grid);
                 eprintln!("{:?}", &cart);
                 unimplemented!();
             }
@@ -222,41 +210,21 @@ impl FromStr for System {
 
 impl fmt::Display for System {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut lines: Vec<String> = self
-            .grid
-            .iter()
-            .map(|row| {
-                row.iter()
-                    .map(|tile| match tile {
-                        Tile::None => ' ',
-                        Tile::Horizontal => '-',
-                        Tile::Vertical => '|',
-                        Tile::PositiveSlope => '\\',
-                        Tile::NegativeSlope => '/',
-                        Tile::Intersection => '+',
-                    })
-                    .collect()
-            })
-            .collect();
-
-        for cart in &self.carts {
-            lines[cart.position.y as usize].replace_range(
-                cart.position.x as usize..=cart.position.x as usize,
-                if cart.direction == Vector2::new(0, -1) {
-                    "^"
-                } else if cart.direction == Vector2::new(0, 1) {
-                    "v"
-                } else if cart.direction == Vector2::new(1, 0) {
-                    ">"
-                } else if cart.direction == Vector2::new(-1, 0) {
-                    "<"
-                } else {
-                    unreachable!()
-                },
-            );
-        }
-
-        write!(f, "{}", lines.join("\n"))
+        let rendered = self.grid.render_with(|p, tile| {
+            let cart = self
+                .carts
+                .iter()
+                .find(|cart| Point::from(cart.position) == p);
+            match cart.map(|cart| (cart.direction.x, cart.direction.y)) {
+                Some((0, -1)) => '^',
+                Some((0, 1)) => 'v',
+                Some((1, 0)) => '>',
+                Some((-1, 0)) => '<',
+                Some(_) => unreachable!(),
+                None => tile.to_char(),
+            }
+        });
+        write!(f, "{}", rendered)
     }
 }
 
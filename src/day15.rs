@@ -1,5 +1,6 @@
-use na::Vector2;
-use std::collections::{HashMap, HashSet, VecDeque};
+use crate::grid::{self, Grid, Point, Tile as _};
+use crate::pathfinding::{Pathfinder, Paths};
+use std::collections::HashSet;
 use std::fmt::{self, Write as _};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -8,6 +9,23 @@ pub enum Tile {
     Cavern,
 }
 
+impl grid::Tile for Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '#' => Some(Tile::Wall),
+            '.' => Some(Tile::Cavern),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Wall => '#',
+            Tile::Cavern => '.',
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Race {
     Goblin,
@@ -16,7 +34,7 @@ pub enum Race {
 
 #[derive(Debug, Clone)]
 pub struct Scene {
-    grid: Vec<Vec<Tile>>,
+    grid: Grid<Tile>,
     entities: Vec<Entity>,
 }
 
@@ -50,8 +68,7 @@ impl Scene {
         }
 
         self.entities.retain(|e| e.is_alive());
-        self.entities
-            .sort_by(|a, b| (a.position.y, a.position.x).cmp(&(b.position.y, b.position.x)));
+        self.entities.sort_by_key(|e| e.position);
 
         full_round
     }
@@ -67,21 +84,21 @@ impl Scene {
         self.entities.iter().filter(|e| e.race == Race::Elf).count()
     }
 
-    fn get_entity_index(&self, position: &Vector2<usize>) -> Option<usize> {
+    fn get_entity_index(&self, position: &Point) -> Option<usize> {
         self.entities.iter().position(|e| e.position == *position)
     }
 
     fn get_adjacent_enemy(&self, me: &Entity) -> Option<usize> {
-        self.get_adjacent_tiles(&me.position)
-            .iter()
-            .filter_map(|e| self.get_entity_index(e))
+        me.position
+            .neighbours4()
+            .filter_map(|p| self.get_entity_index(&p))
             .filter(|&entity_index| {
                 self.entities[entity_index].is_alive() && me.is_enemy(&self.entities[entity_index])
             })
             .min_by_key(|&entity_index| self.entities[entity_index].hp)
     }
 
-    fn get_all_enemies(&self, me: &Entity) -> Vec<Vector2<usize>> {
+    fn get_all_enemies(&self, me: &Entity) -> Vec<Point> {
         self.entities
             .iter()
             .filter(|e| e.is_alive() && me.is_enemy(e))
@@ -89,23 +106,8 @@ impl Scene {
             .collect()
     }
 
-    fn get_adjacent_tiles(&self, position: &Vector2<usize>) -> Vec<Vector2<usize>> {
-        let adjacent = vec![
-            Vector2::new(position.x, position.y - 1),
-            Vector2::new(position.x - 1, position.y),
-            Vector2::new(position.x + 1, position.y),
-            Vector2::new(position.x, position.y + 1),
-        ];
-
-        adjacent
-    }
-
-    fn is_empty(&self, position: &Vector2<usize>) -> bool {
-        if !self.is_inside(position) {
-            return false;
-        }
-
-        if self.grid[position.y][position.x] == Tile::Wall {
+    fn is_empty(&self, position: &Point) -> bool {
+        if self.grid.get(*position) != Some(&Tile::Cavern) {
             return false;
         }
 
@@ -116,94 +118,64 @@ impl Scene {
             .any(|e| e.position == *position)
     }
 
-    fn is_inside(&self, position: &Vector2<usize>) -> bool {
-        !self.grid.is_empty() && self.grid.len() > position.y && self.grid[0].len() > position.x
-    }
-
-    fn get_distances(&self, start: &Vector2<usize>) -> HashMap<Vector2<usize>, usize> {
-        let mut distances = HashMap::new();
-        distances.insert(*start, 0);
-
-        let mut open_set = VecDeque::new();
-        open_set.push_back(*start);
-        let mut closed_set = HashSet::new();
-
-        while let Some(p) = open_set.pop_front() {
-            let successors = self
-                .get_adjacent_tiles(&p)
-                .into_iter()
-                .filter(|c| self.is_empty(c));
-            for s in successors {
-                if closed_set.contains(&s) || open_set.contains(&s) {
-                    continue;
-                }
-
-                open_set.push_back(s);
-                distances.insert(s, distances[&p] + 1);
-            }
-
-            closed_set.insert(p);
-        }
-
-        distances
+    /// Shortest paths over empty squares from `start` to the first square `goal` accepts,
+    /// taking squares in reading order when they are as far.
+    fn get_paths<F: FnMut(&Point) -> bool>(&self, start: Point, goal: F) -> Paths<Point> {
+        let mut empty_neighbours = |p: &Point| {
+            let neighbours: Vec<(Point, usize)> = p
+                .neighbours4()
+                .filter(|n| self.is_empty(n))
+                .map(|n| (n, 1))
+                .collect();
+            neighbours
+        };
+        Pathfinder::new()
+            .tie_break(|&p: &Point| p)
+            .goal(goal)
+            .bfs(&mut empty_neighbours, start)
     }
 
     fn attack(&mut self, attacker_index: usize, enemy_idx: usize) {
         self.entities[enemy_idx].hp -= self.entities[attacker_index].attack_power;
     }
 
-    fn next_step(&self, entity_index: usize) -> Option<Vector2<usize>> {
+    fn next_step(&self, entity_index: usize) -> Option<Point> {
         let me = &self.entities[entity_index];
         self.nearest_enemy(me)
             .and_then(|e| self.nearest_step(&me.position, &e))
     }
 
-    fn nearest_step(&self, me: &Vector2<usize>, enemy: &Vector2<usize>) -> Option<Vector2<usize>> {
-        let distances = self.get_distances(enemy);
-        self.get_adjacent_tiles(me)
-            .into_iter()
-            .filter(|p| self.is_empty(p))
-            .filter_map(|p| distances.get(&p).map(|d| (p, d)))
-            .min_by_key(|&(_, d)| d)
-            .map(|(p, _)| p)
+    fn nearest_step(&self, me: &Point, enemy: &Point) -> Option<Point> {
+        let paths = self.get_paths(*enemy, |p| me.neighbours4().any(|n| n == *p));
+        paths.goal().copied()
     }
 
-    fn nearest_enemy(&self, me: &Entity) -> Option<Vector2<usize>> {
-        let distances = self.get_distances(&me.position);
-        self.get_all_enemies(me)
+    fn nearest_enemy(&self, me: &Entity) -> Option<Point> {
+        let in_range: HashSet<Point> = self
+            .get_all_enemies(me)
             .into_iter()
-            .flat_map(|e| self.get_adjacent_tiles(&e))
+            .flat_map(|e| e.neighbours4())
             .filter(|p| self.is_empty(p))
-            .filter_map(|p| distances.get(&p).map(|d| (p, d)))
-            .min_by_key(|&(_, d)| d)
-            .map(|(p, _)| p)
+            .collect();
+        let paths = self.get_paths(me.position, |p| in_range.contains(p));
+        paths.goal().copied()
     }
 }
 
 impl fmt::Display for Scene {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut lines: Vec<String> = self
-            .grid
-            .iter()
-            .map(|row| {
-                row.iter()
-                    .map(|tile| match tile {
-                        Tile::Cavern => '.',
-                        Tile::Wall => '#',
-                    })
-                    .collect()
-            })
-            .collect();
-
-        for e in &self.entities {
-            lines[e.position.y].replace_range(
-                e.position.x..=e.position.x,
-                match e.race {
-                    Race::Goblin => "G",
-                    Race::Elf => "E",
+        let rendered =
+            self.grid.render_with(
+                |p, tile| match self.entities.iter().find(|e| e.position == p) {
+                    Some(e) if e.race == Race::Goblin => 'G',
+                    Some(_) => 'E',
+                    None => tile.to_char(),
                 },
             );
-            write!(lines[e.position.y], " {:?}({})", e.race, e.hp)?
+        let mut lines: Vec<String> = rendered.lines().map(String::from).collect();
+
+        for e in &self.entities {
+            write!(lines[e.position.y as usize], " {:?}({})", e.race, e.hp)?
         }
 
         writeln!(f, "{}", lines.join("\n"))
@@ -212,14 +184,14 @@ impl fmt::Display for Scene {
 
 #[derive(Debug, Clone)]
 pub struct Entity {
-    position: Vector2<usize>,
+    position: Point,
     race: Race,
     hp: i32,
     attack_power: i32,
 }
 
 impl Entity {
-    pub fn new(position: Vector2<usize>, race: Race) -> Entity {
+    pub fn new(position: Point, race: Race) -> Entity {
         Entity {
             position,
             race,
@@ -255,28 +227,18 @@ pub fn outcome(scene: &mut Scene) -> (i32, i32) {
 #[aoc_generator(day15)]
 fn input_generator(input: &str) -> Scene {
     let mut entities = vec![];
-    let grid = input
-        .lines()
-        .enumerate()
-        .map(|(y, line)| {
-            line.chars()
-                .enumerate()
-                .map(|(x, c)| match c {
-                    '#' => Tile::Wall,
-                    '.' => Tile::Cavern,
-                    'G' => {
-                        entities.push(Entity::new(Vector2::new(x, y), Race::Goblin));
-                        Tile::Cavern
-                    }
-                    'E' => {
-                        entities.push(Entity::new(Vector2::new(x, y), Race::Elf));
-                        Tile::Cavern
-                    }
-                    _ => unreachable!("Input has invalid character"),
-                })
-                .collect()
-        })
-        .collect();
+    let grid = Grid::parse_with(input, |p, c| match c {
+        'G' => {
+            entities.push(Entity::new(p, Race::Goblin));
+            Some(Tile::Cavern)
+        }
+        'E' => {
+            entities.push(Entity::new(p, Race::Elf));
+            Some(Tile::Cavern)
+        }
+        c => Tile::from_char(c),
+    })
+    .unwrap_or_else(|e| panic!("Input has invalid character: {}", e));
 
     Scene { grid, entities }
 }
@@ -1,8 +1,8 @@
+use crate::vm::infer::{self, Inference, Observation};
 use crate::vm::*;
-use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
-use strum::IntoEnumIterator;
 
 #[derive(Debug, Default, Clone)]
 pub struct Sample {
@@ -11,53 +11,21 @@ pub struct Sample {
     after: Registers,
 }
 
-pub fn guess_opcode(samples: &[Sample]) -> HashMap<usize, Opcode> {
-    let mut match_opcodes: Vec<HashSet<Opcode>> = vec![HashSet::new(); OPCODE_COUNT];
-    for (opcode, possible_opcodes) in match_opcodes.iter_mut().enumerate() {
-        let sets: Vec<HashSet<Opcode>> = samples
-            .iter()
-            .filter(|s| s.unknown_instruction.0[0] == opcode)
-            .map(|s| {
-                Opcode::iter()
-                    .filter(|op| {
-                        let instruction =
-                            Instruction::new(op.clone(), &s.unknown_instruction.0[1..4]);
-                        let mut registers = s.before.clone();
-                        instruction.execute(&mut registers);
-
-                        registers == s.after
-                    })
-                    .collect::<HashSet<Opcode>>()
-            })
-            .collect();
-
-        *possible_opcodes = Opcode::iter().collect();
-        for set in sets {
-            *possible_opcodes = possible_opcodes.intersection(&set).cloned().collect();
+impl Sample {
+    pub fn observation(&self) -> Observation {
+        Observation {
+            before: self.before.clone(),
+            instruction: self.unknown_instruction.0,
+            after: self.after.clone(),
         }
     }
+}
 
-    let mut opcode_map = HashMap::new();
-    while opcode_map.len() != OPCODE_COUNT {
-        let confirm_opcodes: Vec<Opcode> = match_opcodes
-            .iter()
-            .enumerate()
-            .filter(|(_, set)| set.len() == 1)
-            .map(|(i, set)| {
-                let opcode = set.iter().next().unwrap();
-                opcode_map.insert(i, opcode.clone());
-                opcode.clone()
-            })
-            .collect();
-
-        match_opcodes.iter_mut().for_each(|set| {
-            confirm_opcodes.iter().for_each(|opcode| {
-                set.remove(opcode);
-            });
-        });
-    }
-
-    opcode_map
+/// Works out the opcode behind each number, reporting ambiguous or contradictory samples
+/// rather than giving up on them.
+pub fn guess_opcode(samples: &[Sample]) -> Inference {
+    let observations: Vec<Observation> = samples.iter().map(Sample::observation).collect();
+    infer::infer(&observations)
 }
 
 #[derive(Debug, Default, Clone)]
@@ -84,47 +52,147 @@ impl FromStr for UnknownInstruction {
 
 pub type RawProgram = Vec<UnknownInstruction>;
 
+/// Renders a raw program as elfcode with mnemonics, given the opcode of each number. The
+/// listing has no `#ip` line, so it loads with `Vm::parse_unbound(listing, Config::DAY16)`.
+pub fn decode(
+    raw_program: &[UnknownInstruction],
+    opcodes: &[Opcode],
+) -> Result<String, Box<dyn Error>> {
+    let mut listing = String::new();
+    for (ip, raw) in raw_program.iter().enumerate() {
+        let opcode = opcodes
+            .get(raw.0[0])
+            .ok_or_else(|| format!("instruction {} has unknown opcode number {}", ip, raw.0[0]))?;
+        let instruction = Instruction::new(opcode.clone(), &raw.0[1..4]);
+        listing += &format!("{}\n", instruction);
+    }
+    Ok(listing)
+}
+
 pub struct Input {
     samples: Vec<Sample>,
     raw_program: RawProgram,
 }
 
-#[aoc_generator(day16)]
-pub fn input_generator(input: &str) -> Input {
-    let mut samples = vec![];
-    let mut sample: Sample = Default::default();
-    let mut sample_end = 0;
-    for (i, line) in input.lines().enumerate() {
-        if i % 4 == 0 {
-            if let Some(begin) = line.find('[') {
-                sample.before = line[begin + 1..line.len() - 1].parse().unwrap();
-            } else {
-                sample_end = i;
-                break;
-            }
-        } else if i % 4 == 1 {
-            sample.unknown_instruction = line.parse().unwrap();
-        } else if i % 4 == 2 {
-            if let Some(begin) = line.find('[') {
-                sample.after = line[begin + 1..line.len() - 1].parse().unwrap();
-                samples.push(sample.clone());
-            } else {
-                sample_end = i;
-                break;
-            }
+impl Input {
+    /// The test program as a named elfcode listing, if the samples pin down every opcode.
+    pub fn listing(&self) -> Result<String, Box<dyn Error>> {
+        match guess_opcode(&self.samples) {
+            Inference::Unique(opcodes) => decode(&self.raw_program, &opcodes),
+            inference => Err(format!("cannot decode the program: {:?}", inference).into()),
         }
     }
+}
 
-    let raw_program = input
+/// Where and why the puzzle input could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputError {
+    /// Index of the sample being parsed, or `None` in the test program.
+    pub sample: Option<usize>,
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.sample {
+            Some(sample) => write!(f, "line {}: sample {}: {}", self.line, sample, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl Error for InputError {}
+
+/// Parses a `Before: [a, b, c, d]` or `After: [a, b, c, d]` line.
+fn register_line(line: &str, label: &str) -> Result<Registers, String> {
+    let list = line
+        .strip_prefix(label)
+        .map(str::trim)
+        .and_then(|rest| rest.strip_prefix('['))
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| format!("expected `{} [a, b, c, d]`, found `{}`", label, line))?;
+    let registers: Registers = list.parse().map_err(|e| format!("{}", e))?;
+    if registers.0.len() != Config::DAY16.registers {
+        return Err(format!(
+            "expected {} registers, found {}",
+            Config::DAY16.registers,
+            registers.0.len()
+        ));
+    }
+    Ok(registers)
+}
+
+fn instruction_line(line: &str) -> Result<UnknownInstruction, String> {
+    line.parse()
+        .map_err(|e| format!("bad instruction `{}`: {}", line, e))
+}
+
+/// Parses the samples, then the test program. Blank lines, surrounding whitespace and CRLF line
+/// endings are ignored anywhere.
+pub fn parse_input(input: &str) -> Result<Input, InputError> {
+    let end = input.lines().count() + 1;
+    let mut lines = input
         .lines()
-        .skip(sample_end)
-        .filter_map(|s| s.parse().ok())
-        .collect();
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .peekable();
+
+    let mut samples = vec![];
+    while let Some(&(_, line)) = lines.peek() {
+        if !line.starts_with("Before:") {
+            break;
+        }
+        let index = samples.len();
+        let mut next = |expected: &str| {
+            lines.next().ok_or_else(|| InputError {
+                sample: Some(index),
+                line: end,
+                message: format!("expected {}, found the end of the input", expected),
+            })
+        };
+        let at = |line| {
+            move |message| InputError {
+                sample: Some(index),
+                line,
+                message,
+            }
+        };
 
-    Input {
+        let (number, line) = next("`Before: [...]`")?;
+        let before = register_line(line, "Before:").map_err(at(number))?;
+        let (number, line) = next("an instruction")?;
+        let unknown_instruction = instruction_line(line).map_err(at(number))?;
+        let (number, line) = next("`After: [...]`")?;
+        let after = register_line(line, "After:").map_err(at(number))?;
+        samples.push(Sample {
+            before,
+            unknown_instruction,
+            after,
+        });
+    }
+
+    let raw_program = lines
+        .map(|(number, line)| {
+            instruction_line(line).map_err(|message| InputError {
+                sample: None,
+                line: number,
+                message,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Input {
         samples,
         raw_program,
-    }
+    })
+}
+
+#[aoc_generator(day16)]
+pub fn input_generator(input: &str) -> Input {
+    parse_input(input).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day16, part1)]
@@ -132,30 +200,112 @@ pub fn part1(input: &Input) -> usize {
     let samples = &input.samples;
     samples
         .iter()
-        .filter(|s| {
-            Opcode::iter()
-                .filter(|op| {
-                    let instruction = Instruction::new(op.clone(), &s.unknown_instruction.0[1..4]);
-                    let mut registers = s.before.clone();
-                    instruction.execute(&mut registers);
-
-                    registers == s.after
-                })
-                .count()
-                >= 3
-        })
+        .filter(|s| s.observation().candidates().len() >= 3)
         .count()
 }
 
 #[aoc(day16, part2)]
 pub fn part2(input: &Input) -> usize {
-    let opcode_map = guess_opcode(&input.samples);
-    let mut registers: Registers = Default::default();
-    input
-        .raw_program
-        .iter()
-        .map(|i| Instruction::new(opcode_map[&i.0[0]].clone(), &i.0[1..4]))
-        .for_each(|i| i.execute(&mut registers));
+    let listing = input.listing().unwrap();
+    let mut vm = Vm::parse_unbound(&listing, Config::DAY16).unwrap();
+    vm.run().unwrap();
+    vm.registers.0[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::samples::{self, Generator};
+
+    #[test]
+    fn synthetic_puzzles() {
+        for seed in 0..1000 {
+            let numbering = samples::shuffled(seed);
+            let unobserved = seed as usize % 4;
+            let puzzle = Generator::new(numbering.clone(), seed)
+                .unobserved(unobserved)
+                .extra_samples(2)
+                .generate();
+            let input = input_generator(&puzzle.input);
 
-    registers.0[0]
+            match guess_opcode(&input.samples) {
+                Inference::Unique(opcode_map) => {
+                    assert!(unobserved < 2, "seed {}", seed);
+                    assert_eq!(opcode_map, numbering, "seed {}", seed);
+                    assert_eq!(part2(&input), puzzle.registers.0[0], "seed {}", seed);
+                    assert!(input.listing().is_ok());
+                }
+                Inference::Ambiguous(candidates) => {
+                    assert!(unobserved >= 2, "seed {}", seed);
+                    assert_eq!(candidates.ambiguous(), puzzle.unobserved, "seed {}", seed);
+                    assert!(input.listing().is_err());
+                }
+                Inference::Conflict(samples) => panic!("seed {}: conflict in {:?}", seed, samples),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_tolerant() {
+        let input = "Before: [3, 2, 1, 1]\r\n9 2 1 2\r\nAfter:  [3, 2, 2, 1]\r\n\r\n\r\n  Before:[0,1,2,3]\n\t1 0 0 1 \nAfter: [0, 0, 2, 3]\n\n\n\n\n7 1 2 3\r\n\r\n5 0 0 0\r\n";
+        let input = parse_input(input).unwrap();
+        assert_eq!(input.samples.len(), 2);
+        assert_eq!(input.samples[1].before.0, [0, 1, 2, 3]);
+        assert_eq!(input.samples[1].unknown_instruction.0, [1, 0, 0, 1]);
+        assert_eq!(input.raw_program.len(), 2);
+        assert_eq!(part1(&input), 2);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let sample = "Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]\n\n";
+        let error = |input: &str| parse_input(input).err().unwrap();
+
+        let e = error(&format!(
+            "{}Before: [3, 2, 1, 1]\n9 2 1\nAfter: [3, 2, 2, 1]",
+            sample
+        ));
+        assert_eq!((e.sample, e.line), (Some(1), 6));
+        assert_eq!(
+            e.to_string(),
+            "line 6: sample 1: bad instruction `9 2 1`: Code len != 4"
+        );
+
+        let e = error(&format!(
+            "{}Before: [3, 2, 1, 1]\n9 2 1 2\n\n1 2 3 4",
+            sample
+        ));
+        assert_eq!((e.sample, e.line), (Some(1), 8));
+        assert!(e.message.starts_with("expected `After: [a, b, c, d]`"));
+
+        let e = error("Before: [3, 2, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]");
+        assert_eq!((e.sample, e.line), (Some(0), 1));
+
+        let e = error("Before: [3, 2, 1, 1]\n9 2 1 2");
+        assert_eq!((e.sample, e.line), (Some(0), 3));
+
+        let e = error(&format!("{}\n\n1 2 3 4\n1 x 3 4", sample));
+        assert_eq!((e.sample, e.line), (None, 8));
+    }
+
+    #[test]
+    fn decode_program() {
+        let numbering = samples::shuffled(3);
+        let number = |opcode: Opcode| numbering.iter().position(|o| *o == opcode).unwrap();
+        let raw_program: RawProgram = vec![
+            format!("{} 7 0 1", number(Opcode::Seti)).parse().unwrap(),
+            format!("{} 1 3 2", number(Opcode::Muli)).parse().unwrap(),
+            format!("{} 2 1 0", number(Opcode::Addr)).parse().unwrap(),
+        ];
+        let listing = decode(&raw_program, &numbering).unwrap();
+        assert_eq!(listing, "seti 7 0 1\nmuli 1 3 2\naddr 2 1 0\n");
+
+        let mut vm: Vm = Vm::parse_unbound(&listing, Config::DAY16).unwrap();
+        assert_eq!(disasm::disassemble(&vm).lines[1].statement, "r2 = r1 * 3");
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[..4], [28, 7, 21, 0]);
+
+        let bad: RawProgram = vec!["16 0 0 0".parse().unwrap()];
+        assert!(decode(&bad, &numbering).is_err());
+    }
 }
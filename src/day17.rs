@@ -1,31 +1,8 @@
-use na::Vector2;
+use crate::grid::{self, Point, SparseGrid};
 use regex::Regex;
-use std::cmp::Ordering;
-use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Vec2(Vector2<usize>);
-
-impl Vec2 {
-    pub fn new(x: usize, y: usize) -> Vec2 {
-        Vec2(Vector2::new(x, y))
-    }
-}
-
-impl Ord for Vec2 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        (self.0.y, self.0.x).cmp(&(other.0.y, other.0.x))
-    }
-}
-
-impl PartialOrd for Vec2 {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Tile {
     Sand,
@@ -34,41 +11,54 @@ pub enum Tile {
     FlowingWater,
 }
 
-impl fmt::Display for Tile {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let tile = match self {
+impl grid::Tile for Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '.' => Some(Tile::Sand),
+            '#' => Some(Tile::Clay),
+            '~' => Some(Tile::RestWater),
+            '|' => Some(Tile::FlowingWater),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
             Tile::Sand => '.',
             Tile::Clay => '#',
             Tile::RestWater => '~',
             Tile::FlowingWater => '|',
-        };
-        write!(f, "{}", tile)
+        }
     }
 }
 
 #[derive(Debug, Default, Clone)]
-pub struct Grid(BTreeMap<Vec2, Tile>);
+pub struct Grid(SparseGrid<Tile>);
 
 impl Grid {
     pub fn water_count(&self) -> usize {
-        let min_y = (self.0.iter().find(|&(_, t)| *t == Tile::Clay).unwrap().0)
-            .0
-            .y;
+        let min_y = self.min_clay_y();
         self.0
             .iter()
-            .filter(|&(p, t)| p.0.y >= min_y && (*t == Tile::FlowingWater || *t == Tile::RestWater))
+            .filter(|&(p, t)| p.y >= min_y && (*t == Tile::FlowingWater || *t == Tile::RestWater))
             .count()
     }
 
     pub fn rest_water_count(&self) -> usize {
-        let min_y = (self.0.iter().find(|&(_, t)| *t == Tile::Clay).unwrap().0)
-            .0
-            .y;
+        let min_y = self.min_clay_y();
         self.0
             .iter()
-            .filter(|&(p, t)| p.0.y >= min_y && *t == Tile::RestWater)
+            .filter(|&(p, t)| p.y >= min_y && *t == Tile::RestWater)
             .count()
     }
+
+    fn min_clay_y(&self) -> i32 {
+        self.0.iter().find(|&(_, t)| *t == Tile::Clay).unwrap().0.y
+    }
+
+    fn max_y(&self) -> i32 {
+        self.0.last().unwrap().0.y
+    }
 }
 
 impl FromStr for Grid {
@@ -83,10 +73,10 @@ impl FromStr for Grid {
 
             if &cap[1] == "x" {
                 grid.0
-                    .extend(range.map(|y| (Vec2::new(fixed_coordinate, y), Tile::Clay)));
+                    .extend(range.map(|y| (Point::new(fixed_coordinate, y), Tile::Clay)));
             } else {
                 grid.0
-                    .extend(range.map(|x| (Vec2::new(x, fixed_coordinate), Tile::Clay)));
+                    .extend(range.map(|x| (Point::new(x, fixed_coordinate), Tile::Clay)));
             }
         }
 
@@ -96,14 +86,15 @@ impl FromStr for Grid {
 
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for y in 0..=(self.0.iter().next_back().unwrap().0).0.y {
-            for x in 450..=550 {
-                let tile = self.0.get(&Vec2(Vector2::new(x, y))).unwrap_or(&Tile::Sand);
-                write!(f, "{}", tile)?;
-            }
-            writeln!(f)?;
-        }
-        write!(f, "")
+        write!(
+            f,
+            "{}",
+            self.0.render(
+                Point::new(450, 0),
+                Point::new(550, self.max_y()),
+                &Tile::Sand
+            )
+        )
     }
 }
 
@@ -114,54 +105,39 @@ pub enum WaterDirection {
     Right,
 }
 
-pub fn fill(grid: &mut Grid, position: &Vec2, direction: WaterDirection) -> Option<usize> {
-    let max_y = (grid.0.iter().next_back().unwrap().0).0.y;
-    if position.0.y > max_y {
+pub fn fill(grid: &mut Grid, position: &Point, direction: WaterDirection) -> Option<i32> {
+    if position.y > grid.max_y() {
         return None;
     }
 
-    match grid.0.get(position).unwrap_or(&Tile::Sand) {
+    let left = Point::new(position.x - 1, position.y);
+    let right = Point::new(position.x + 1, position.y);
+    match grid.0.get(*position).unwrap_or(&Tile::Sand) {
         Tile::Sand => {
-            grid.0.insert(position.clone(), Tile::FlowingWater);
+            grid.0.insert(*position, Tile::FlowingWater);
             fill(
                 grid,
-                &Vec2::new(position.0.x, position.0.y + 1),
+                &Point::new(position.x, position.y + 1),
                 WaterDirection::Both,
             )?;
             match direction {
                 WaterDirection::Both => match (
-                    fill(
-                        grid,
-                        &Vec2::new(position.0.x - 1, position.0.y),
-                        WaterDirection::Left,
-                    ),
-                    fill(
-                        grid,
-                        &Vec2::new(position.0.x + 1, position.0.y),
-                        WaterDirection::Right,
-                    ),
+                    fill(grid, &left, WaterDirection::Left),
+                    fill(grid, &right, WaterDirection::Right),
                 ) {
                     (Some(l), Some(r)) => {
                         (l + 1..r).for_each(|x| {
-                            grid.0.insert(Vec2::new(x, position.0.y), Tile::RestWater);
+                            grid.0.insert(Point::new(x, position.y), Tile::RestWater);
                         });
-                        Some(position.0.x)
+                        Some(position.x)
                     }
                     _ => None,
                 },
-                WaterDirection::Left => fill(
-                    grid,
-                    &Vec2::new(position.0.x - 1, position.0.y),
-                    WaterDirection::Left,
-                ),
-                WaterDirection::Right => fill(
-                    grid,
-                    &Vec2::new(position.0.x + 1, position.0.y),
-                    WaterDirection::Right,
-                ),
+                WaterDirection::Left => fill(grid, &left, WaterDirection::Left),
+                WaterDirection::Right => fill(grid, &right, WaterDirection::Right),
             }
         }
-        Tile::Clay | Tile::RestWater => Some(position.0.x),
+        Tile::Clay | Tile::RestWater => Some(position.x),
         Tile::FlowingWater => None,
     }
 }
@@ -174,14 +150,14 @@ pub fn input_generator(input: &str) -> Grid {
 #[aoc(day17, part1)]
 pub fn solve_part1(grid: &Grid) -> usize {
     let mut grid = grid.to_owned();
-    fill(&mut grid, &Vec2::new(500, 0), WaterDirection::Both);
+    fill(&mut grid, &Point::new(500, 0), WaterDirection::Both);
     grid.water_count()
 }
 
 #[aoc(day17, part2)]
 pub fn solve_part2(grid: &Grid) -> usize {
     let mut grid = grid.to_owned();
-    fill(&mut grid, &Vec2::new(500, 0), WaterDirection::Both);
+    fill(&mut grid, &Point::new(500, 0), WaterDirection::Both);
     grid.rest_water_count()
 }
 
@@ -201,14 +177,14 @@ y=13, x=498..504";
     #[test]
     fn part1() {
         let mut grid = input_generator(TEST_INPUT);
-        fill(&mut grid, &Vec2::new(500, 0), WaterDirection::Both);
+        fill(&mut grid, &Point::new(500, 0), WaterDirection::Both);
         assert_eq!(grid.water_count(), 57);
     }
 
     #[test]
     fn part2() {
         let mut grid = input_generator(TEST_INPUT);
-        fill(&mut grid, &Vec2::new(500, 0), WaterDirection::Both);
+        fill(&mut grid, &Point::new(500, 0), WaterDirection::Both);
         assert_eq!(grid.rest_water_count(), 29);
     }
 }
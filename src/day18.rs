@@ -1,3 +1,4 @@
+use crate::grid::{self, Point};
 use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
@@ -9,32 +10,27 @@ pub enum Tile {
     Lumberyard,
 }
 
-impl FromStr for Tile {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "." => Tile::Open,
-            "|" => Tile::Trees,
-            "#" => Tile::Lumberyard,
-            _ => unreachable!(),
-        })
+impl grid::Tile for Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '.' => Some(Tile::Open),
+            '|' => Some(Tile::Trees),
+            '#' => Some(Tile::Lumberyard),
+            _ => None,
+        }
     }
-}
 
-impl fmt::Display for Tile {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match self {
-            Tile::Open => ".",
-            Tile::Trees => "|",
-            Tile::Lumberyard => "#",
-        };
-        write!(f, "{}", s)
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Open => '.',
+            Tile::Trees => '|',
+            Tile::Lumberyard => '#',
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Grid(Vec<Vec<Tile>>);
+pub struct Grid(grid::Grid<Tile>);
 
 impl Grid {
     pub fn resource_value(&self) -> usize {
@@ -42,7 +38,7 @@ impl Grid {
     }
 
     pub fn tile_count(&self, tile_type: &Tile) -> usize {
-        self.0.iter().flatten().filter(|&t| t == tile_type).count()
+        self.0.iter().filter(|&(_, t)| t == tile_type).count()
     }
 
     pub fn run(&mut self, times: usize) {
@@ -64,26 +60,24 @@ impl Grid {
 
     pub fn tick(&mut self) {
         let mut new_grid = self.0.clone();
-        for (y, row) in new_grid.iter_mut().enumerate() {
-            for (x, tile) in row.iter_mut().enumerate() {
-                let stats = self.adjacent(x, y);
-                match self.0[y][x] {
-                    Tile::Open => {
-                        if *stats.get(&Tile::Trees).unwrap_or(&0) >= 3 {
-                            *tile = Tile::Trees;
-                        }
+        for (p, tile) in new_grid.iter_mut() {
+            let stats = self.adjacent(p);
+            match self.0[p] {
+                Tile::Open => {
+                    if *stats.get(&Tile::Trees).unwrap_or(&0) >= 3 {
+                        *tile = Tile::Trees;
                     }
-                    Tile::Trees => {
-                        if *stats.get(&Tile::Lumberyard).unwrap_or(&0) >= 3 {
-                            *tile = Tile::Lumberyard;
-                        }
+                }
+                Tile::Trees => {
+                    if *stats.get(&Tile::Lumberyard).unwrap_or(&0) >= 3 {
+                        *tile = Tile::Lumberyard;
                     }
-                    Tile::Lumberyard => {
-                        if *stats.get(&Tile::Lumberyard).unwrap_or(&0) == 0
-                            || *stats.get(&Tile::Trees).unwrap_or(&0) == 0
-                        {
-                            *tile = Tile::Open;
-                        }
+                }
+                Tile::Lumberyard => {
+                    if *stats.get(&Tile::Lumberyard).unwrap_or(&0) == 0
+                        || *stats.get(&Tile::Trees).unwrap_or(&0) == 0
+                    {
+                        *tile = Tile::Open;
                     }
                 }
             }
@@ -92,52 +86,29 @@ impl Grid {
         self.0 = new_grid;
     }
 
-    fn adjacent(&self, center_x: usize, center_y: usize) -> HashMap<Tile, usize> {
+    fn adjacent(&self, center: Point) -> HashMap<Tile, usize> {
         let mut stats: HashMap<Tile, usize> = HashMap::new();
-        for y in center_y.saturating_sub(1)..=center_y + 1 {
-            for x in center_x.saturating_sub(1)..=center_x + 1 {
-                if x == center_x && y == center_y {
-                    continue;
-                }
-
-                if y < self.0.len() && x < self.0[0].len() {
-                    stats
-                        .entry(self.0[y][x].clone())
-                        .and_modify(|e| *e += 1)
-                        .or_insert(1);
-                }
-            }
+        for p in self.0.neighbours8(center) {
+            stats
+                .entry(self.0[p].clone())
+                .and_modify(|e| *e += 1)
+                .or_insert(1);
         }
         stats
     }
 }
 
 impl FromStr for Grid {
-    type Err = ();
+    type Err = grid::ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Grid(
-            s.lines()
-                .map(|line| {
-                    line.chars()
-                        .map(|c| c.to_string().parse().unwrap())
-                        .collect()
-                })
-                .collect(),
-        ))
+        s.parse().map(Grid)
     }
 }
 
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for y in 0..self.0.len() {
-            for x in 0..self.0[0].len() {
-                write!(f, "{}", self.0[y][x])?;
-            }
-            writeln!(f)?;
-        }
-
-        Ok(())
+        write!(f, "{}", self.0)
     }
 }
 
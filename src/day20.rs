@@ -1,7 +1,5 @@
-use na::Vector2;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::VecDeque;
+use crate::grid::{self, Point, SparseGrid};
+use crate::pathfinding::{Pathfinder, Paths};
 use std::fmt;
 use std::str::FromStr;
 
@@ -12,42 +10,50 @@ pub enum Tile {
     Room,
 }
 
-impl fmt::Display for Tile {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let c = match self {
-            Tile::Wall => "#",
-            Tile::Door => "+",
-            Tile::Room => " ",
-        };
-        write!(f, "{}", c)
+impl grid::Tile for Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '#' => Some(Tile::Wall),
+            '+' => Some(Tile::Door),
+            ' ' => Some(Tile::Room),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Wall => '#',
+            Tile::Door => '+',
+            Tile::Room => ' ',
+        }
     }
 }
 
 #[derive(Debug, Default, Clone)]
-pub struct Grid(HashMap<Vector2<i32>, Tile>);
+pub struct Grid(SparseGrid<Tile>);
 
 impl Grid {
     pub fn pass_most_doors(&self) -> usize {
-        let distances = self.get_distances(Vector2::zeros());
+        let distances = self.get_distances(Point::default());
         distances
             .iter()
-            .filter(|(p, _)| self.0.get(p) == Some(&Tile::Room))
+            .filter(|&(&p, _)| self.0.get(p) == Some(&Tile::Room))
             .map(|(_, d)| d / 2)
             .max()
             .unwrap()
     }
 
     pub fn pass_doors_more_than(&self, num: usize) -> usize {
-        let distances = self.get_distances(Vector2::zeros());
+        let distances = self.get_distances(Point::default());
         distances
             .iter()
-            .filter(|(p, _)| self.0.get(p) == Some(&Tile::Room))
+            .filter(|&(&p, _)| self.0.get(p) == Some(&Tile::Room))
             .map(|(_, d)| d / 2)
             .filter(|&d| d >= num)
             .count()
     }
 
-    fn add_room(&mut self, mut current: Vector2<i32>, direction: Vector2<i32>) -> Vector2<i32> {
+    fn add_room(&mut self, mut current: Point, direction: Point) -> Point {
         current += direction;
         self.0.insert(current, Tile::Door);
         current += direction;
@@ -56,45 +62,9 @@ impl Grid {
         current
     }
 
-    fn get_distances(&self, start: Vector2<i32>) -> HashMap<Vector2<i32>, usize> {
-        let mut distances = HashMap::new();
-        distances.insert(start, 0);
-
-        let mut open_set = VecDeque::new();
-        open_set.push_back(start);
-        let mut closed_set = HashSet::new();
-
-        while let Some(p) = open_set.pop_front() {
-            let successors = self
-                .get_adjacent_tiles(p)
-                .into_iter()
-                .filter(|&c| self.is_empty(c));
-            for s in successors {
-                if closed_set.contains(&s) || open_set.contains(&s) {
-                    continue;
-                }
-
-                open_set.push_back(s);
-                distances.insert(s, distances[&p] + 1);
-            }
-
-            closed_set.insert(p);
-        }
-
-        distances
-    }
-
-    fn get_adjacent_tiles(&self, position: Vector2<i32>) -> Vec<Vector2<i32>> {
-        vec![
-            Vector2::new(position.x, position.y - 1),
-            Vector2::new(position.x - 1, position.y),
-            Vector2::new(position.x + 1, position.y),
-            Vector2::new(position.x, position.y + 1),
-        ]
-    }
-
-    fn is_empty(&self, position: Vector2<i32>) -> bool {
-        self.0.contains_key(&position)
+    fn get_distances(&self, start: Point) -> Paths<Point> {
+        let mut open = |&p: &Point| self.0.neighbours4(p).map(|n| (n, 1));
+        Pathfinder::new().bfs(&mut open, start)
     }
 }
 
@@ -104,16 +74,16 @@ impl FromStr for Grid {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let s = &s[1..s.len() - 1];
         let mut grid: Self = Default::default();
-        let mut current = Vector2::zeros();
+        let mut current = Point::default();
         grid.0.insert(current, Tile::Room);
         let mut stack = vec![];
 
         for c in s.chars() {
             match c {
-                'E' => current = grid.add_room(current, Vector2::new(1, 0)),
-                'S' => current = grid.add_room(current, Vector2::new(0, -1)),
-                'W' => current = grid.add_room(current, Vector2::new(-1, 0)),
-                'N' => current = grid.add_room(current, Vector2::new(0, 1)),
+                'E' => current = grid.add_room(current, Point::new(1, 0)),
+                'S' => current = grid.add_room(current, Point::new(0, 1)),
+                'W' => current = grid.add_room(current, Point::new(-1, 0)),
+                'N' => current = grid.add_room(current, Point::new(0, -1)),
                 '(' => stack.push(current),
                 ')' => current = stack.pop().expect("Parentheses not match"),
                 '|' => current = *stack.last().unwrap(),
@@ -127,23 +97,13 @@ impl FromStr for Grid {
 
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let min_x = self.0.iter().map(|(p, _)| p.x).min().unwrap();
-        let max_x = self.0.iter().map(|(p, _)| p.x).max().unwrap();
-        let min_y = self.0.iter().map(|(p, _)| p.y).min().unwrap();
-        let max_y = self.0.iter().map(|(p, _)| p.y).max().unwrap();
-
-        for y in (min_y - 1..=max_y + 1).rev() {
-            for x in min_x - 1..=max_x + 1 {
-                write!(
-                    f,
-                    "{}",
-                    self.0.get(&Vector2::new(x, y)).unwrap_or(&Tile::Wall)
-                )?;
-            }
-            writeln!(f)?;
-        }
-
-        Ok(())
+        let (min, max) = self.0.bounds().unwrap();
+        let margin = Point::new(1, 1);
+        write!(
+            f,
+            "{}",
+            self.0.render(min - margin, max + margin, &Tile::Wall)
+        )
     }
 }
 
@@ -1,33 +1,52 @@
 use crate::vm::*;
-use std::collections::HashSet;
 
+/// The program halts when register 0 equals the register compared against it by its only
+/// `eqrr` involving register 0. Returns the ip of that comparison and the other register.
+fn halting_comparison(vm: &Vm) -> (usize, usize) {
+    vm.program
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| instruction.opcode == Opcode::Eqrr)
+        .find_map(
+            |(ip, instruction)| match (instruction.input1, instruction.input2) {
+                (0, other) | (other, 0) if other != 0 && other != vm.ip_index() => {
+                    Some((ip, other))
+                }
+                _ => None,
+            },
+        )
+        .expect("no eqrr compares against register 0")
+}
+
+/// Tapping the compared register yields every value of register 0 that would halt the
+/// program, in order.
 #[aoc(day21, part1)]
 pub fn solve_part1(input: &str) -> usize {
     let mut vm: Vm = input.parse().unwrap();
-    while *vm.ip() != 28 {
-        vm.step();
-    }
-
-    vm.registers.0[vm.program[28].input1]
+    let (ip, register) = halting_comparison(&vm);
+    vm.taps.push(Tap { ip, register });
+    vm.outputs().next().unwrap()
 }
 
+/// The last value to show up for the first time before the values start repeating halts the
+/// program last.
 #[aoc(day21, part2)]
 pub fn solve_part2(input: &str) -> usize {
-    let mut vm: Vm = input.parse().unwrap();
-    let mut numbers = HashSet::new();
-    let mut last = 0;
+    let vm: Vm = input.parse().unwrap();
+    let (ip, register) = halting_comparison(&vm);
+    vm.find_cycle(ip, register).unwrap().unwrap().last
+}
 
-    loop {
-        vm.step();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY21_SAMPLE;
 
-        if *vm.ip() == 28 {
-            if numbers.insert(vm.registers.0[vm.program[28].input1]) {
-                last = vm.registers.0[vm.program[28].input1];
-            } else {
-                break;
-            }
-        }
+    #[test]
+    fn locate_comparison() {
+        let vm: Vm = DAY21_SAMPLE.parse().unwrap();
+        assert_eq!(halting_comparison(&vm), (1, 1));
+        assert_eq!(solve_part1(DAY21_SAMPLE), 1);
+        assert_eq!(solve_part2(DAY21_SAMPLE), 17);
     }
-
-    last
 }
@@ -1,7 +1,7 @@
+use crate::pathfinding::{Graph, Pathfinder};
 use na::Vector2;
 use num_derive::FromPrimitive;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::HashMap;
 use strum::{EnumCount, IntoEnumIterator};
 use strum_macros::EnumCount;
 
@@ -25,29 +25,6 @@ pub struct PositionAndTool {
     tool: Tool,
 }
 
-#[derive(Debug)]
-pub struct MinScored(usize, PositionAndTool);
-
-impl Ord for MinScored {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.0.cmp(&other.0).reverse()
-    }
-}
-
-impl PartialOrd for MinScored {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl PartialEq for MinScored {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(other) == Ordering::Equal
-    }
-}
-
-impl Eq for MinScored {}
-
 #[derive(Debug, Clone)]
 pub struct Cave {
     depth: usize,
@@ -105,70 +82,69 @@ impl Cave {
     }
 
     pub fn spent_minutes(&mut self) -> usize {
-        let mut visited = HashSet::new();
-        let mut visit_next = BinaryHeap::new();
-        visit_next.push(MinScored(
-            0,
-            PositionAndTool {
-                position: Vector2::zeros(),
-                tool: Tool::Torch,
-            },
-        ));
-
+        let start = PositionAndTool {
+            position: Vector2::zeros(),
+            tool: Tool::Torch,
+        };
         let target = PositionAndTool {
             position: self.target,
             tool: Tool::Torch,
         };
 
-        while let Some(MinScored(minutes, current)) = visit_next.pop() {
-            // println!("{} {} {} {:?}", minutes, current.position.x, current.position.y, current.tool);
-            if visited.contains(&current) {
-                continue;
-            }
-
-            if current == target {
-                return minutes;
-            }
-
-            for tool in Tool::iter() {
-                if tool != current.tool
-                    && tool as usize != self.region_type(current.position) as usize
-                {
-                    visit_next.push(MinScored(
-                        minutes + 7,
-                        PositionAndTool {
-                            position: current.position,
-                            tool,
-                        },
-                    ));
-                }
-            }
-
-            for (dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
-                let new_x = current.position.x as i64 + dx;
-                let new_y = current.position.y as i64 + dy;
-                if new_x < 0 || new_y < 0 {
-                    continue;
-                }
+        // Each step costs at least a minute, and the torch still has to be equipped.
+        let heuristic = |current: &PositionAndTool| {
+            let distance = current.position.x.abs_diff(target.position.x)
+                + current.position.y.abs_diff(target.position.y);
+            distance + if current.tool == target.tool { 0 } else { 7 }
+        };
+        Pathfinder::new()
+            .goal(|current| *current == target)
+            .astar(self, start, heuristic)
+            .cost(&target)
+            .unwrap()
+    }
+}
 
-                let position = Vector2::new(new_x as usize, new_y as usize);
-                if self.region_type(position) as usize == current.tool as usize {
-                    continue;
-                }
+impl Graph<PositionAndTool> for Cave {
+    type Neighbours = Vec<(PositionAndTool, usize)>;
 
-                visit_next.push(MinScored(
-                    minutes + 1,
+    fn neighbours(&mut self, current: &PositionAndTool) -> Self::Neighbours {
+        let mut neighbours = vec![];
+        for tool in Tool::iter() {
+            if tool != current.tool && tool as usize != self.region_type(current.position) as usize
+            {
+                neighbours.push((
                     PositionAndTool {
-                        position,
-                        tool: current.tool,
+                        position: current.position,
+                        tool,
                     },
+                    7,
                 ));
             }
+        }
+
+        for (dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let new_x = current.position.x as i64 + dx;
+            let new_y = current.position.y as i64 + dy;
+            if new_x < 0 || new_y < 0 {
+                continue;
+            }
+
+            let position = Vector2::new(new_x as usize, new_y as usize);
+            if self.region_type(position) as usize == current.tool as usize {
+                continue;
+            }
 
-            visited.insert(current);
+            neighbours.push((
+                PositionAndTool {
+                    position,
+                    tool: current.tool,
+                },
+                1,
+            ));
         }
 
-        unreachable!()
+        neighbours
     }
 }
 
@@ -0,0 +1,455 @@
+use na::Vector2;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, AddAssign, Index, IndexMut, Sub};
+use std::str::FromStr;
+
+/// A position on a map, with `y` growing downwards. Points are ordered in reading order: top
+/// to bottom, then left to right.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+const NEIGHBOURS4: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const NEIGHBOURS8: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    /// The points above, left, right and below, in reading order.
+    pub fn neighbours4(self) -> impl Iterator<Item = Point> {
+        NEIGHBOURS4
+            .iter()
+            .map(move |&(x, y)| self + Point::new(x, y))
+    }
+
+    /// The eight surrounding points, in reading order.
+    pub fn neighbours8(self) -> impl Iterator<Item = Point> {
+        NEIGHBOURS8
+            .iter()
+            .map(move |&(x, y)| self + Point::new(x, y))
+    }
+}
+
+impl Ord for Point {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Point {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Point {
+    fn add_assign(&mut self, other: Point) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl From<Vector2<i32>> for Point {
+    fn from(v: Vector2<i32>) -> Point {
+        Point::new(v.x, v.y)
+    }
+}
+
+impl From<Point> for Vector2<i32> {
+    fn from(p: Point) -> Vector2<i32> {
+        Vector2::new(p.x, p.y)
+    }
+}
+
+/// A tile that is drawn as a single character on a map.
+pub trait Tile: Sized {
+    fn from_char(c: char) -> Option<Self>;
+    fn to_char(&self) -> char;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub point: Point,
+    pub found: char,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unexpected {:?} at {},{}",
+            self.found, self.point.x, self.point.y
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// A rectangular map with a tile at every point from `(0, 0)` up to its size.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    tiles: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, tile: T) -> Grid<T>
+    where
+        T: Clone,
+    {
+        Grid {
+            width,
+            height,
+            tiles: vec![tile; width * height],
+        }
+    }
+
+    /// Parses a character map, turning each character into a tile with `tile`. Rows shorter
+    /// than the longest one are padded with spaces.
+    pub fn parse_with<F>(s: &str, mut tile: F) -> Result<Grid<T>, ParseError>
+    where
+        F: FnMut(Point, char) -> Option<T>,
+    {
+        let lines: Vec<&str> = s.lines().collect();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut tiles = Vec::with_capacity(width * lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            let chars = line.chars().chain(std::iter::repeat(' '));
+            for (x, c) in chars.take(width).enumerate() {
+                let point = Point::new(x as i32, y as i32);
+                tiles.push(tile(point, c).ok_or(ParseError { point, found: c })?);
+            }
+        }
+
+        Ok(Grid {
+            width,
+            height: lines.len(),
+            tiles,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= 0 && p.y >= 0 && (p.x as usize) < self.width && (p.y as usize) < self.height
+    }
+
+    pub fn get(&self, p: Point) -> Option<&T> {
+        self.index_of(p).map(|i| &self.tiles[i])
+    }
+
+    pub fn get_mut(&mut self, p: Point) -> Option<&mut T> {
+        self.index_of(p).map(move |i| &mut self.tiles[i])
+    }
+
+    /// Every point in reading order.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let width = self.width;
+        (0..self.width * self.height)
+            .map(move |i| Point::new((i % width) as i32, (i / width) as i32))
+    }
+
+    /// Every tile with its point, in reading order.
+    pub fn iter(&self) -> impl Iterator<Item = (Point, &T)> {
+        self.points().zip(self.tiles.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Point, &mut T)> {
+        self.points().zip(self.tiles.iter_mut())
+    }
+
+    /// The points of `neighbours4` that are on the grid.
+    pub fn neighbours4(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        p.neighbours4().filter(move |&n| self.contains(n))
+    }
+
+    /// The points of `neighbours8` that are on the grid.
+    pub fn neighbours8(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        p.neighbours8().filter(move |&n| self.contains(n))
+    }
+
+    /// Draws the grid a row per line, turning each tile into a character with `draw`.
+    pub fn render_with<F>(&self, mut draw: F) -> String
+    where
+        F: FnMut(Point, &T) -> char,
+    {
+        let mut s = String::with_capacity((self.width + 1) * self.height);
+        for (p, tile) in self.iter() {
+            s.push(draw(p, tile));
+            if p.x as usize == self.width - 1 {
+                s.push('\n');
+            }
+        }
+        s
+    }
+
+    fn index_of(&self, p: Point) -> Option<usize> {
+        if self.contains(p) {
+            Some(p.y as usize * self.width + p.x as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Index<Point> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, p: Point) -> &T {
+        self.get(p)
+            .unwrap_or_else(|| panic!("{:?} is outside the grid", p))
+    }
+}
+
+impl<T> IndexMut<Point> for Grid<T> {
+    fn index_mut(&mut self, p: Point) -> &mut T {
+        self.get_mut(p)
+            .unwrap_or_else(|| panic!("{:?} is outside the grid", p))
+    }
+}
+
+impl<T: Tile> FromStr for Grid<T> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Grid::parse_with(s, |_, c| T::from_char(c))
+    }
+}
+
+impl<T: Tile> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render_with(|_, tile| tile.to_char()))
+    }
+}
+
+/// A map that only stores the points it has a tile for, and may extend in any direction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SparseGrid<T> {
+    tiles: BTreeMap<Point, T>,
+}
+
+impl<T> Default for SparseGrid<T> {
+    fn default() -> Self {
+        SparseGrid {
+            tiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> SparseGrid<T> {
+    pub fn new() -> SparseGrid<T> {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        self.tiles.contains_key(&p)
+    }
+
+    pub fn get(&self, p: Point) -> Option<&T> {
+        self.tiles.get(&p)
+    }
+
+    pub fn get_mut(&mut self, p: Point) -> Option<&mut T> {
+        self.tiles.get_mut(&p)
+    }
+
+    pub fn insert(&mut self, p: Point, tile: T) -> Option<T> {
+        self.tiles.insert(p, tile)
+    }
+
+    pub fn remove(&mut self, p: Point) -> Option<T> {
+        self.tiles.remove(&p)
+    }
+
+    /// The first tile in reading order.
+    pub fn first(&self) -> Option<(Point, &T)> {
+        self.tiles.iter().next().map(|(&p, tile)| (p, tile))
+    }
+
+    /// The last tile in reading order.
+    pub fn last(&self) -> Option<(Point, &T)> {
+        self.tiles.iter().next_back().map(|(&p, tile)| (p, tile))
+    }
+
+    /// The smallest and largest coordinates among the stored points.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let first = self.first()?.0;
+        Some(self.tiles.keys().fold((first, first), |(min, max), p| {
+            (
+                Point::new(min.x.min(p.x), min.y.min(p.y)),
+                Point::new(max.x.max(p.x), max.y.max(p.y)),
+            )
+        }))
+    }
+
+    /// Every stored tile with its point, in reading order.
+    pub fn iter(&self) -> impl Iterator<Item = (Point, &T)> {
+        self.tiles.iter().map(|(&p, tile)| (p, tile))
+    }
+
+    /// The points of `neighbours4` that have a tile.
+    pub fn neighbours4(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        p.neighbours4().filter(move |&n| self.contains(n))
+    }
+
+    /// The points of `neighbours8` that have a tile.
+    pub fn neighbours8(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        p.neighbours8().filter(move |&n| self.contains(n))
+    }
+
+    /// Draws the rectangle from `min` to `max` inclusive a row per line, with `background`
+    /// wherever there is no tile.
+    pub fn render(&self, min: Point, max: Point, background: &T) -> String
+    where
+        T: Tile,
+    {
+        let mut s = String::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                s.push(self.get(Point::new(x, y)).unwrap_or(background).to_char());
+            }
+            s.push('\n');
+        }
+        s
+    }
+}
+
+impl<T> Extend<(Point, T)> for SparseGrid<T> {
+    fn extend<I: IntoIterator<Item = (Point, T)>>(&mut self, iter: I) {
+        self.tiles.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Cell {
+        Open,
+        Wall,
+    }
+
+    impl Tile for Cell {
+        fn from_char(c: char) -> Option<Cell> {
+            match c {
+                '.' => Some(Cell::Open),
+                '#' => Some(Cell::Wall),
+                _ => None,
+            }
+        }
+
+        fn to_char(&self) -> char {
+            match self {
+                Cell::Open => '.',
+                Cell::Wall => '#',
+            }
+        }
+    }
+
+    const MAP: &str = "#####\n#..##\n#...#\n#####\n";
+
+    #[test]
+    fn dense() {
+        let grid: Grid<Cell> = MAP.parse().unwrap();
+        assert_eq!((grid.width(), grid.height()), (5, 4));
+        assert_eq!(grid.to_string(), MAP);
+        assert_eq!(grid.get(Point::new(1, 1)), Some(&Cell::Open));
+        assert_eq!(grid.get(Point::new(5, 1)), None);
+        assert_eq!(grid.get(Point::new(-1, 1)), None);
+
+        let open: Vec<Point> = grid
+            .iter()
+            .filter(|&(_, tile)| *tile == Cell::Open)
+            .map(|(p, _)| p)
+            .collect();
+        let mut sorted = open.clone();
+        sorted.sort();
+        assert_eq!(open, sorted);
+        assert_eq!(open[2], Point::new(1, 2));
+
+        let around: Vec<Point> = grid.neighbours4(Point::new(0, 1)).collect();
+        assert_eq!(
+            around,
+            [Point::new(0, 0), Point::new(1, 1), Point::new(0, 2)]
+        );
+        assert_eq!(grid.neighbours8(Point::new(0, 0)).count(), 3);
+        assert_eq!(grid.neighbours8(Point::new(2, 2)).count(), 8);
+
+        assert_eq!(
+            "#.\n#x".parse::<Grid<Cell>>(),
+            Err(ParseError {
+                point: Point::new(1, 1),
+                found: 'x',
+            })
+        );
+        let ragged = Grid::parse_with("##\n#", |_, c| Some(c)).unwrap();
+        assert_eq!(ragged[Point::new(1, 1)], ' ');
+    }
+
+    #[test]
+    fn sparse() {
+        let mut grid = SparseGrid::new();
+        grid.insert(Point::new(2, -1), Cell::Wall);
+        grid.insert(Point::new(-3, 1), Cell::Wall);
+        grid.insert(Point::new(0, 1), Cell::Open);
+        assert_eq!(grid.first(), Some((Point::new(2, -1), &Cell::Wall)));
+        assert_eq!(grid.last(), Some((Point::new(0, 1), &Cell::Open)));
+        assert_eq!(grid.bounds(), Some((Point::new(-3, -1), Point::new(2, 1))));
+        assert_eq!(grid.neighbours4(Point::new(0, 0)).count(), 1);
+        assert_eq!(
+            grid.render(Point::new(-3, -1), Point::new(2, 1), &Cell::Open),
+            ".....#\n......\n#.....\n"
+        );
+    }
+}
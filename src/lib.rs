@@ -4,6 +4,8 @@ extern crate nalgebra as na;
 #[macro_use]
 extern crate strum_macros;
 
+pub mod grid;
+pub mod pathfinding;
 pub mod vm;
 
 pub mod day1;
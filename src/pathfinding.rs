@@ -0,0 +1,345 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+/// A graph given by the states reachable in one move from each state, with the cost of
+/// each move.
+///
+/// Closures from `&S` to an iterator of `(S, usize)` are graphs too.
+pub trait Graph<S> {
+    type Neighbours: IntoIterator<Item = (S, usize)>;
+
+    fn neighbours(&mut self, state: &S) -> Self::Neighbours;
+}
+
+impl<S, I, F> Graph<S> for F
+where
+    F: FnMut(&S) -> I,
+    I: IntoIterator<Item = (S, usize)>,
+{
+    type Neighbours = I;
+
+    fn neighbours(&mut self, state: &S) -> I {
+        self(state)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node<S> {
+    cost: usize,
+    parent: Option<S>,
+    settled: bool,
+}
+
+/// The cheapest paths found by a search, from its start to every state it settled.
+#[derive(Debug, Clone)]
+pub struct Paths<S> {
+    nodes: HashMap<S, Node<S>>,
+    goal: Option<S>,
+}
+
+impl<S: Clone + Eq + Hash> Paths<S> {
+    /// The goal the search stopped at, if it had one and reached it.
+    pub fn goal(&self) -> Option<&S> {
+        self.goal.as_ref()
+    }
+
+    pub fn cost(&self, state: &S) -> Option<usize> {
+        self.node(state).map(|node| node.cost)
+    }
+
+    /// The state before `state` on the path to it, or `None` for the start.
+    pub fn parent(&self, state: &S) -> Option<&S> {
+        self.node(state).and_then(|node| node.parent.as_ref())
+    }
+
+    /// The states from the start to `state`, both included.
+    pub fn path(&self, state: &S) -> Option<Vec<S>> {
+        self.node(state)?;
+        let mut path = vec![state.clone()];
+        while let Some(parent) = self.parent(path.last().unwrap()) {
+            path.push(parent.clone());
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Every settled state with its cost.
+    pub fn iter(&self) -> impl Iterator<Item = (&S, usize)> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.settled)
+            .map(|(state, node)| (state, node.cost))
+    }
+
+    fn node(&self, state: &S) -> Option<&Node<S>> {
+        self.nodes.get(state).filter(|node| node.settled)
+    }
+}
+
+/// A queued state, ordered so that `BinaryHeap` pops the lowest priority first, then the
+/// lowest tie-break key, then the earliest pushed.
+struct Queued<S, K> {
+    priority: usize,
+    key: K,
+    order: usize,
+    cost: usize,
+    state: S,
+}
+
+impl<S, K: Ord> Ord for Queued<S, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, &self.key, self.order)
+            .cmp(&(other.priority, &other.key, other.order))
+            .reverse()
+    }
+}
+
+impl<S, K: Ord> PartialOrd for Queued<S, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S, K: Ord> PartialEq for Queued<S, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S, K: Ord> Eq for Queued<S, K> {}
+
+type Goal<'a, S> = Box<dyn FnMut(&S) -> bool + 'a>;
+
+/// Configures and runs a search over a `Graph`.
+///
+/// States that are equally far from the start are expanded in the order given by
+/// `tie_break`, or in the order they were found without one. Among equally cheap paths to a
+/// state, the one through the parent that `tie_break` orders first is kept.
+pub struct Pathfinder<'a, S, K = ()> {
+    goal: Option<Goal<'a, S>>,
+    tie_break: Box<dyn Fn(&S) -> K + 'a>,
+}
+
+impl<'a, S: Clone + Eq + Hash> Pathfinder<'a, S> {
+    pub fn new() -> Pathfinder<'a, S> {
+        Pathfinder {
+            goal: None,
+            tie_break: Box::new(|_| ()),
+        }
+    }
+}
+
+impl<'a, S: Clone + Eq + Hash> Default for Pathfinder<'a, S> {
+    fn default() -> Self {
+        Pathfinder::new()
+    }
+}
+
+impl<'a, S: Clone + Eq + Hash, K: Ord> Pathfinder<'a, S, K> {
+    /// Stops the search at the first state that satisfies `goal`. Without a goal, the search
+    /// settles every reachable state.
+    pub fn goal<F>(mut self, goal: F) -> Pathfinder<'a, S, K>
+    where
+        F: FnMut(&S) -> bool + 'a,
+    {
+        self.goal = Some(Box::new(goal));
+        self
+    }
+
+    /// Breaks ties between states by the key `tie_break` gives them, lowest first.
+    pub fn tie_break<L: Ord, F>(self, tie_break: F) -> Pathfinder<'a, S, L>
+    where
+        F: Fn(&S) -> L + 'a,
+    {
+        Pathfinder {
+            goal: self.goal,
+            tie_break: Box::new(tie_break),
+        }
+    }
+
+    /// Breadth-first search, which treats every move as costing 1.
+    pub fn bfs<G: Graph<S>>(self, graph: &mut G, start: S) -> Paths<S> {
+        let mut unit = |state: &S| {
+            let neighbours: Vec<(S, usize)> = graph
+                .neighbours(state)
+                .into_iter()
+                .map(|(next, _)| (next, 1))
+                .collect();
+            neighbours
+        };
+        self.search(&mut unit, start, |_| 0)
+    }
+
+    pub fn dijkstra<G: Graph<S>>(self, graph: &mut G, start: S) -> Paths<S> {
+        self.search(graph, start, |_| 0)
+    }
+
+    /// A* search. `heuristic` must never overestimate the cost from a state to the nearest
+    /// goal, and must not drop by more than the cost of any move, or the paths found may not
+    /// be the cheapest.
+    pub fn astar<G, H>(self, graph: &mut G, start: S, heuristic: H) -> Paths<S>
+    where
+        G: Graph<S>,
+        H: FnMut(&S) -> usize,
+    {
+        self.search(graph, start, heuristic)
+    }
+
+    fn search<G, H>(mut self, graph: &mut G, start: S, mut heuristic: H) -> Paths<S>
+    where
+        G: Graph<S>,
+        H: FnMut(&S) -> usize,
+    {
+        let mut nodes = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let mut order = 0;
+        nodes.insert(
+            start.clone(),
+            Node {
+                cost: 0,
+                parent: None,
+                settled: false,
+            },
+        );
+        queue.push(Queued {
+            priority: heuristic(&start),
+            key: (self.tie_break)(&start),
+            order,
+            cost: 0,
+            state: start,
+        });
+
+        while let Some(Queued { cost, state, .. }) = queue.pop() {
+            let node = nodes.get_mut(&state).unwrap();
+            if node.settled || node.cost < cost {
+                continue;
+            }
+            node.settled = true;
+
+            if self.goal.as_mut().is_some_and(|goal| goal(&state)) {
+                return Paths {
+                    nodes,
+                    goal: Some(state),
+                };
+            }
+
+            for (next, step) in graph.neighbours(&state) {
+                let next_cost = cost + step;
+                match nodes.entry(next.clone()) {
+                    Entry::Occupied(mut entry) => {
+                        let node = entry.get_mut();
+                        if node.settled || node.cost < next_cost {
+                            continue;
+                        }
+                        if node.cost == next_cost {
+                            // Same cost: only the parent may change, and it is not queued again.
+                            let better = node.parent.as_ref().is_some_and(|parent| {
+                                (self.tie_break)(&state) < (self.tie_break)(parent)
+                            });
+                            if better {
+                                node.parent = Some(state.clone());
+                            }
+                            continue;
+                        }
+                        node.cost = next_cost;
+                        node.parent = Some(state.clone());
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(Node {
+                            cost: next_cost,
+                            parent: Some(state.clone()),
+                            settled: false,
+                        });
+                    }
+                }
+                order += 1;
+                queue.push(Queued {
+                    priority: next_cost + heuristic(&next),
+                    key: (self.tie_break)(&next),
+                    order,
+                    cost: next_cost,
+                    state: next,
+                });
+            }
+        }
+
+        Paths { nodes, goal: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Grid, Point};
+
+    const MAZE: &str = "\
+#######
+#.....#
+#.#.#.#
+#.....#
+###.###
+#.....#
+#######
+";
+
+    fn open(grid: &Grid<char>) -> impl FnMut(&Point) -> Vec<(Point, usize)> + '_ {
+        move |&p| {
+            grid.neighbours4(p)
+                .filter(|&n| grid[n] == '.')
+                .map(|n| (n, 1))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn bfs() {
+        let grid = Grid::parse_with(MAZE, |_, c| Some(c)).unwrap();
+        let start = Point::new(1, 1);
+        let paths = Pathfinder::new().bfs(&mut open(&grid), start);
+        assert_eq!(paths.goal(), None);
+        assert_eq!(paths.iter().count(), 19);
+        assert_eq!(paths.cost(&Point::new(5, 5)), Some(8));
+        assert_eq!(paths.cost(&Point::new(0, 0)), None);
+
+        // Both routes around the pillar at (2, 2) are as short; reading order goes along the
+        // top row.
+        let paths = Pathfinder::new()
+            .tie_break(|&p: &Point| p)
+            .goal(|&p| p == Point::new(3, 3))
+            .bfs(&mut open(&grid), start);
+        assert_eq!(paths.goal(), Some(&Point::new(3, 3)));
+        assert_eq!(
+            paths.path(&Point::new(3, 3)).unwrap(),
+            [(1, 1), (2, 1), (3, 1), (3, 2), (3, 3)]
+                .iter()
+                .map(|&(x, y)| Point::new(x, y))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(paths.parent(&start), None);
+    }
+
+    #[test]
+    fn weighted() {
+        // A line of states where skipping ahead costs more than walking, except from 0 to 5.
+        let mut graph = |&n: &usize| {
+            let mut next = vec![(n + 1, 2), (n + 2, 5)];
+            if n == 0 {
+                next.push((5, 9));
+            }
+            next
+        };
+        let paths = Pathfinder::new().goal(|&n| n == 5).dijkstra(&mut graph, 0);
+        assert_eq!(paths.cost(&5), Some(9));
+        assert_eq!(paths.path(&5), Some(vec![0, 5]));
+
+        let paths = Pathfinder::new()
+            .goal(|&n| n == 6)
+            .astar(&mut graph, 0, |&n| 6usize.saturating_sub(n));
+        assert_eq!(paths.path(&6), Some(vec![0, 5, 6]));
+        assert_eq!(paths.cost(&6), Some(11));
+        // A* settles nothing beyond the goal's cost, so 7 and 8 are never settled.
+        assert_eq!(paths.cost(&7), None);
+    }
+}
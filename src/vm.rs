@@ -1,27 +1,125 @@
+use std::collections::VecDeque;
+use std::fmt;
 use std::str::FromStr;
+use std::time::Instant;
+
+pub mod asm;
+pub mod cfg;
+pub mod compiled;
+mod cycle;
+pub mod debugger;
+pub mod disasm;
+mod error;
+mod history;
+pub mod idiom;
+pub mod infer;
+pub mod isa;
+mod limits;
+mod output;
+pub mod samples;
+pub mod search;
+mod snapshot;
+pub mod symbolic;
+#[cfg(test)]
+pub(crate) mod test_programs;
+pub mod trace;
+pub mod transpile;
+mod word;
+
+use self::history::History;
+
+pub use self::cycle::*;
+pub use self::error::*;
+pub use self::limits::*;
+pub use self::output::*;
+pub use self::word::*;
 
 pub const OPCODE_COUNT: usize = 16;
 
+/// Splits `s` on whitespace, yielding each token with its 1-based column.
+fn tokens(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    s.split_whitespace()
+        .map(move |token| (token.as_ptr() as usize - s.as_ptr() as usize + 1, token))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Registers(pub Vec<usize>);
+pub struct Registers<W = usize>(pub Vec<W>);
 
-impl FromStr for Registers {
-    type Err = ();
+impl<W: Word> FromStr for Registers<W> {
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Registers(
-            s.split(',').map(|s| s.trim().parse().unwrap()).collect(),
-        ))
+        let mut column = 1;
+        s.split(',')
+            .map(|part| {
+                let token = part.trim();
+                let token_column = column + part.find(token).unwrap_or(0);
+                column += part.len() + 1;
+                token.parse().map_err(|_| {
+                    ParseError::new(ParseErrorKind::NonNumericRegister, token_column, token)
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Registers)
     }
 }
 
-impl Default for Registers {
+impl<W: Word> Registers<W> {
+    pub fn new(config: Config) -> Registers<W> {
+        Registers(vec![W::zero(); config.registers])
+    }
+
+    pub fn read(&self, index: usize) -> Result<W, FaultKind> {
+        self.0
+            .get(index)
+            .cloned()
+            .ok_or(FaultKind::BadRegister(index))
+    }
+
+    pub fn write(&mut self, index: usize, value: W) -> Result<(), FaultKind> {
+        let register = self.0.get_mut(index).ok_or(FaultKind::BadRegister(index))?;
+        *register = value;
+        Ok(())
+    }
+}
+
+impl<W: Word> Default for Registers<W> {
     fn default() -> Self {
-        Registers(vec![0; 6])
+        Registers::new(Config::default())
+    }
+}
+
+/// How `addr`, `addi`, `mulr` and `muli` behave when the result does not fit in a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ArithmeticMode {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+impl ArithmeticMode {
+    pub fn add<W: Word>(self, a: W, b: W) -> Result<W, FaultKind> {
+        match self {
+            ArithmeticMode::Checked => a.checked_add(&b).ok_or(FaultKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_add(&b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_add(&b)),
+        }
+    }
+
+    pub fn mul<W: Word>(self, a: W, b: W) -> Result<W, FaultKind> {
+        match self {
+            ArithmeticMode::Checked => a.checked_mul(&b).ok_or(FaultKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_mul(&b)),
+            ArithmeticMode::Saturating => Ok(a.saturating_mul(&b)),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, EnumIter, EnumString)]
+/// The 16 built-in opcodes, plus `Custom` for those registered with an `isa::InstructionSet`.
+/// `Opcode::iter()` and `from_str` only cover the built-in ones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EnumIter, EnumString, IntoStaticStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum Opcode {
     Addr,
@@ -40,9 +138,43 @@ pub enum Opcode {
     Eqir,
     Eqri,
     Eqrr,
+    #[strum(disabled)]
+    Custom(isa::CustomOpcode),
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Opcode::Custom(custom) => write!(f, "{}", custom.mnemonic),
+            opcode => write!(f, "{}", <&'static str>::from(opcode)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Register,
+    Immediate,
+    Ignored,
+}
+
+impl Opcode {
+    /// How the two inputs of an instruction are interpreted; the output is always a register.
+    pub fn operands(&self) -> (Operand, Operand) {
+        use self::Opcode::*;
+        use self::Operand::*;
+        match self {
+            Addr | Mulr | Banr | Borr | Gtrr | Eqrr => (Register, Register),
+            Addi | Muli | Bani | Bori | Gtri | Eqri => (Register, Immediate),
+            Gtir | Eqir => (Immediate, Register),
+            Setr => (Register, Ignored),
+            Seti => (Immediate, Ignored),
+            Custom(custom) => custom.operands,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Instruction {
     pub opcode: Opcode,
     pub input1: usize,
@@ -60,127 +192,617 @@ impl Instruction {
         }
     }
 
-    pub fn execute(&self, registers: &mut Registers) {
+    pub fn execute<W: Word>(&self, registers: &mut Registers<W>) -> Result<(), FaultKind> {
+        self.execute_with(registers, ArithmeticMode::default())
+    }
+
+    /// Immediates that do not fit in the word type fault with `FaultKind::Overflow`. Custom
+    /// opcodes fault with `FaultKind::UnknownOpcode`; they run through their instruction set.
+    pub fn execute_with<W: Word>(
+        &self,
+        registers: &mut Registers<W>,
+        mode: ArithmeticMode,
+    ) -> Result<(), FaultKind> {
         use self::Opcode::*;
-        match self.opcode {
-            Addr => registers.0[self.output] = registers.0[self.input1] + registers.0[self.input2],
-            Addi => registers.0[self.output] = registers.0[self.input1] + self.input2,
-            Mulr => registers.0[self.output] = registers.0[self.input1] * registers.0[self.input2],
-            Muli => registers.0[self.output] = registers.0[self.input1] * self.input2,
-            Banr => registers.0[self.output] = registers.0[self.input1] & registers.0[self.input2],
-            Bani => registers.0[self.output] = registers.0[self.input1] & self.input2,
-            Borr => registers.0[self.output] = registers.0[self.input1] | registers.0[self.input2],
-            Bori => registers.0[self.output] = registers.0[self.input1] | self.input2,
-            Setr => registers.0[self.output] = registers.0[self.input1],
-            Seti => registers.0[self.output] = self.input1,
-            Gtir => {
-                registers.0[self.output] = if self.input1 > registers.0[self.input2] {
-                    1
-                } else {
-                    0
-                }
+        let reg = |index| registers.read(index);
+        let imm = |value| W::from_usize(value).ok_or(FaultKind::Overflow);
+        let flag = |condition| if condition { W::one() } else { W::zero() };
+        let value = match self.opcode {
+            Addr => mode.add(reg(self.input1)?, reg(self.input2)?)?,
+            Addi => mode.add(reg(self.input1)?, imm(self.input2)?)?,
+            Mulr => mode.mul(reg(self.input1)?, reg(self.input2)?)?,
+            Muli => mode.mul(reg(self.input1)?, imm(self.input2)?)?,
+            Banr => reg(self.input1)?.bitand(&reg(self.input2)?),
+            Bani => reg(self.input1)?.bitand(&imm(self.input2)?),
+            Borr => reg(self.input1)?.bitor(&reg(self.input2)?),
+            Bori => reg(self.input1)?.bitor(&imm(self.input2)?),
+            Setr => reg(self.input1)?,
+            Seti => imm(self.input1)?,
+            Gtir => flag(imm(self.input1)? > reg(self.input2)?),
+            Gtri => flag(reg(self.input1)? > imm(self.input2)?),
+            Gtrr => flag(reg(self.input1)? > reg(self.input2)?),
+            Eqir => flag(imm(self.input1)? == reg(self.input2)?),
+            Eqri => flag(reg(self.input1)? == imm(self.input2)?),
+            Eqrr => flag(reg(self.input1)? == reg(self.input2)?),
+            Custom(_) => return Err(FaultKind::UnknownOpcode),
+        };
+
+        registers.write(self.output, value)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.opcode, self.input1, self.input2, self.output
+        )
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_instruction(s, |mnemonic| mnemonic.parse().ok())
+    }
+}
+
+fn parse_instruction(
+    s: &str,
+    opcode: impl Fn(&str) -> Option<Opcode>,
+) -> Result<Instruction, ParseError> {
+    let mut iter = tokens(s);
+    let end = s.trim_end().len() + 1;
+
+    let (column, mnemonic) = iter
+        .next()
+        .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownMnemonic, end, ""))?;
+    let opcode = opcode(mnemonic)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownMnemonic, column, mnemonic))?;
+
+    let (kind1, kind2) = opcode.operands();
+    let mut operand = |kind| {
+        let (column, token) = iter
+            .next()
+            .ok_or_else(|| ParseError::new(ParseErrorKind::MissingOperand, end, ""))?;
+        token.parse().map_err(|_| {
+            let kind = if kind == Operand::Register {
+                ParseErrorKind::NonNumericRegister
+            } else {
+                ParseErrorKind::NonNumericImmediate
+            };
+            ParseError::new(kind, column, token)
+        })
+    };
+
+    let input1 = operand(kind1)?;
+    let input2 = operand(kind2)?;
+    let output = operand(Operand::Register)?;
+
+    if let Some((column, token)) = iter.next() {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            token,
+        ));
+    }
+
+    Ok(Instruction {
+        opcode,
+        input1,
+        input2,
+        output,
+    })
+}
+
+/// An elfcode machine. `W` is the word type held in each register.
+#[derive(Debug, Clone)]
+pub struct Vm<W = usize> {
+    pub registers: Registers<W>,
+    pub program: Vec<Instruction>,
+    pub instruction_set: isa::InstructionSet<W>,
+    pub arithmetic: ArithmeticMode,
+    /// Whether `run` and `run_with` execute recognised idioms natively, counting each as one step.
+    /// On by default. Observers and the debugger turn it off, since they must see every
+    /// instruction.
+    pub accelerate: bool,
+    pub taps: Vec<Tap>,
+    ip_index: usize,
+    output: VecDeque<W>,
+    steps: usize,
+    history: History<W>,
+}
+
+impl<W: Word> Vm<W> {
+    pub fn run(&mut self) -> Result<(), Fault> {
+        self.run_with(Limits::new()).map(|_| ())
+    }
+
+    pub fn run_with(&mut self, mut limits: Limits<W>) -> Result<StopReason, Fault> {
+        let start = Instant::now();
+        let mut executed = 0;
+        let idioms: Vec<idiom::Idiom> = if self.accelerate && limits.observers.is_empty() {
+            idiom::detect(&self.program, self.ip_index)
+                .into_iter()
+                .filter(|idiom| {
+                    let body = idiom.start() + 1..idiom.exit();
+                    !limits.breakpoints.iter().any(|ip| body.contains(ip))
+                        && !self
+                            .taps
+                            .iter()
+                            .any(|tap| tap.ip == idiom.start() || body.contains(&tap.ip))
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        loop {
+            if self.current_ip()? >= self.program.len() {
+                return Ok(StopReason::Halted);
             }
-            Gtri => {
-                registers.0[self.output] = if registers.0[self.input1] > self.input2 {
-                    1
-                } else {
-                    0
-                }
+            if limits.max_steps.is_some_and(|max| executed >= max) {
+                return Ok(StopReason::StepLimit);
             }
-            Gtrr => {
-                registers.0[self.output] = if registers.0[self.input1] > registers.0[self.input2] {
-                    1
-                } else {
-                    0
+            if let Some(budget) = limits.time_budget {
+                if executed % CLOCK_INTERVAL == 0 && start.elapsed() >= budget {
+                    return Ok(StopReason::TimeLimit);
                 }
             }
-            Eqir => {
-                registers.0[self.output] = if self.input1 == registers.0[self.input2] {
-                    1
-                } else {
-                    0
+
+            let ip = self.current_ip()?;
+            match idioms.iter().find(|idiom| idiom.start() == ip) {
+                Some(idiom) if self.apply_idiom(idiom) => {}
+                _ if limits.observers.is_empty() => self.step()?,
+                _ => {
+                    let before = self.registers.clone();
+                    self.step()?;
+                    for observer in limits.observers.iter_mut() {
+                        observer.observe(ip, &self.program[ip], &before, &self.registers);
+                    }
                 }
             }
-            Eqri => {
-                registers.0[self.output] = if registers.0[self.input1] == self.input2 {
-                    1
-                } else {
-                    0
-                }
+            executed += 1;
+
+            if limits.stop_on_output && !self.output.is_empty() {
+                return Ok(StopReason::Output);
             }
-            Eqrr => {
-                registers.0[self.output] = if registers.0[self.input1] == registers.0[self.input2] {
-                    1
-                } else {
-                    0
-                }
+            let ip = self.current_ip()?;
+            if limits.breakpoints.contains(&ip) {
+                return Ok(StopReason::Breakpoint(ip));
+            }
+            if let Some(index) = limits
+                .predicates
+                .iter_mut()
+                .position(|predicate| predicate(&self.registers))
+            {
+                return Ok(StopReason::Predicate(index));
             }
         }
     }
-}
 
-impl FromStr for Instruction {
-    type Err = ();
+    pub fn ip_index(&self) -> usize {
+        self.ip_index
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut iter = s.split_whitespace();
+    fn apply_idiom(&mut self, idiom: &idiom::Idiom) -> bool {
+        let undo = self.history.capture_all(&self.registers);
+        if !idiom.apply(&mut self.registers, self.ip_index) {
+            return false;
+        }
+        if let Some(undo) = undo {
+            self.history.push(undo);
+        }
+        self.steps += 1;
+        true
+    }
 
-        macro_rules! parse {
-            ($iter:expr) => {
-                $iter.next().and_then(|part| part.parse().ok()).unwrap()
-            };
+    /// Instructions executed so far, counting each accelerated idiom as one.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Runs the program lazily, yielding each value emitted by an `out` instruction or a tap.
+    pub fn outputs(&mut self) -> Outputs<'_, W> {
+        Outputs::new(self)
+    }
+
+    /// Removes the oldest emitted value that has not been taken yet.
+    pub fn take_output(&mut self) -> Option<W> {
+        self.output.pop_front()
+    }
+
+    /// The ip as an index into the program. Values that are negative or too large for a
+    /// `usize` are past the end, so they halt the program.
+    ///
+    /// Fails if the registers have been replaced by too few to hold the ip. There is no ip to
+    /// report then, so the fault gives `usize::MAX`, as for an ip too large to represent.
+    fn current_ip(&self) -> Result<usize, Fault> {
+        let value = self.registers.read(self.ip_index).map_err(|kind| Fault {
+            kind,
+            ip: usize::MAX,
+            instruction: None,
+        })?;
+        Ok(value.to_usize().unwrap_or(usize::MAX))
+    }
+
+    pub fn step_observed(&mut self, observer: &mut dyn trace::Observer<W>) -> Result<(), Fault> {
+        let ip = self.current_ip()?;
+        let before = self.registers.clone();
+        self.step()?;
+        observer.observe(ip, &self.program[ip], &before, &self.registers);
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<(), Fault> {
+        let ip = self.current_ip()?;
+        let instruction = self.program.get(ip).ok_or(Fault {
+            kind: FaultKind::IpOutOfBounds,
+            ip,
+            instruction: None,
+        })?;
+        let fault = |kind| Fault {
+            kind,
+            ip,
+            instruction: Some(instruction.clone()),
+        };
+
+        let undo = match instruction.opcode {
+            Opcode::Custom(_) => self.history.capture_all(&self.registers),
+            _ => self
+                .history
+                .capture_step(&self.registers, instruction.output, self.ip_index),
+        };
+
+        for tap in self.taps.iter().filter(|tap| tap.ip == ip) {
+            let value = self.registers.read(tap.register).map_err(fault)?;
+            self.output.push_back(value);
         }
 
-        Ok(Instruction {
-            opcode: parse!(iter),
-            input1: parse!(iter),
-            input2: parse!(iter),
-            output: parse!(iter),
+        let effect = self
+            .instruction_set
+            .execute(instruction, &mut self.registers, self.arithmetic)
+            .map_err(fault)?;
+        let ip_value = self.registers.read(self.ip_index).map_err(fault)?;
+        let next = match effect {
+            isa::Effect::Halt => W::from_usize(self.program.len()).ok_or(FaultKind::Overflow),
+            isa::Effect::Continue => self.arithmetic.add(ip_value, W::one()),
+            isa::Effect::Output(value) => {
+                self.output.push_back(value);
+                self.arithmetic.add(ip_value, W::one())
+            }
+        };
+        self.registers
+            .write(self.ip_index, next.map_err(fault)?)
+            .map_err(fault)?;
+        self.steps += 1;
+        if let Some(undo) = undo {
+            self.history.push(undo);
+        }
+
+        Ok(())
+    }
+
+    /// Parses a program for a machine shaped by `config`. Unlike `from_str`, this rejects
+    /// instructions that name registers the machine does not have.
+    pub fn parse_with(s: &str, config: Config) -> Result<Vm<W>, ParseError> {
+        Vm::parse(s, config, true, false, isa::InstructionSet::new())
+    }
+
+    /// Parses a program without an `#ip` line, such as a decoded day16 program, validating
+    /// registers as `parse_with` does. The ip lives in an extra register after the ones in
+    /// `config`, which instructions cannot name.
+    pub fn parse_unbound(s: &str, config: Config) -> Result<Vm<W>, ParseError> {
+        Vm::parse(s, config, true, true, isa::InstructionSet::new())
+    }
+
+    fn parse(
+        s: &str,
+        config: Config,
+        validate: bool,
+        unbound: bool,
+        instruction_set: isa::InstructionSet<W>,
+    ) -> Result<Vm<W>, ParseError> {
+        let mut line_iter = s.lines().enumerate();
+        let ip_index = if unbound {
+            config.registers
+        } else {
+            parse_ip_directive(
+                line_iter.next().map_or("", |(_, line)| line),
+                config.registers,
+            )?
+        };
+        let program = line_iter
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let instruction = instruction_set
+                    .parse_instruction(line)
+                    .map_err(|e| e.at_line(i + 1))?;
+                if validate {
+                    check_registers(&instruction, line, config).map_err(|e| e.at_line(i + 1))?;
+                }
+                Ok(instruction)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let registers = Registers::new(Config {
+            registers: config.registers + unbound as usize,
+        });
+        Ok(Vm {
+            registers,
+            program,
+            instruction_set,
+            arithmetic: Default::default(),
+            accelerate: true,
+            taps: vec![],
+            ip_index,
+            output: VecDeque::new(),
+            steps: 0,
+            history: History::new(0),
         })
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Vm {
-    pub registers: Registers,
-    pub program: Vec<Instruction>,
-    ip_index: usize,
+impl Vm {
+    pub fn ip(&mut self) -> Result<&mut usize, FaultKind> {
+        self.registers
+            .0
+            .get_mut(self.ip_index)
+            .ok_or(FaultKind::BadRegister(self.ip_index))
+    }
 }
 
-impl Vm {
-    pub fn run(&mut self) {
-        while *self.ip() < self.program.len() {
-            self.step();
+fn check_registers(
+    instruction: &Instruction,
+    line: &str,
+    config: Config,
+) -> Result<(), ParseError> {
+    let (kind1, kind2) = instruction.opcode.operands();
+    let operands = [
+        (kind1, instruction.input1),
+        (kind2, instruction.input2),
+        (Operand::Register, instruction.output),
+    ];
+    for ((kind, value), (column, token)) in operands.iter().zip(tokens(line).skip(1)) {
+        if *kind == Operand::Register && *value >= config.registers {
+            return Err(ParseError::new(
+                ParseErrorKind::NoSuchRegister,
+                column,
+                token,
+            ));
         }
     }
+    Ok(())
+}
+
+fn parse_ip_directive(line: &str, register_count: usize) -> Result<usize, ParseError> {
+    let mut iter = tokens(line);
+    let bad = |column, token| ParseError::new(ParseErrorKind::BadIpDirective, column, token);
 
-    pub fn ip(&mut self) -> &mut usize {
-        &mut self.registers.0[self.ip_index]
+    match iter.next() {
+        Some((_, "#ip")) => {}
+        Some((column, token)) => return Err(bad(column, token)),
+        None => return Err(bad(1, "")),
     }
 
-    pub fn step(&mut self) {
-        let ip = *self.ip();
-        self.program[ip].execute(&mut self.registers);
-        *self.ip() += 1;
+    let ip_index = match iter.next() {
+        Some((column, token)) => match token.parse() {
+            Ok(ip_index) if ip_index < register_count => ip_index,
+            _ => return Err(bad(column, token)),
+        },
+        None => return Err(bad(line.trim_end().len() + 1, "")),
+    };
+
+    if let Some((column, token)) = iter.next() {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            token,
+        ));
     }
+
+    Ok(ip_index)
 }
 
-impl FromStr for Vm {
-    type Err = ();
+/// Parses a program for the default six-register machine. Register operands are only checked
+/// when executed; use `Vm::parse_with` to reject bad ones up front.
+impl<W: Word> FromStr for Vm<W> {
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut line_iter = s.lines();
-        let ip_index = line_iter
-            .next()
-            .and_then(|line| line.split_whitespace().next_back())
-            .and_then(|ip| ip.parse().ok())
+        Vm::parse(
+            s,
+            Config::default(),
+            false,
+            false,
+            isa::InstructionSet::new(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn parse_error(s: &str) -> ParseError {
+        s.parse::<Vm>().unwrap_err()
+    }
+
+    #[test]
+    fn parse_program() {
+        let vm: Vm = "#ip 0\nseti 5 0 1\n\naddr 1 2 3\n".parse().unwrap();
+        assert_eq!(vm.ip_index, 0);
+        assert_eq!(vm.program.len(), 2);
+        assert_eq!(vm.program[1].opcode, Opcode::Addr);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = parse_error("#ip 0\nseti 5 0 1\nadr 1 2 3");
+        assert_eq!(error.kind, ParseErrorKind::UnknownMnemonic);
+        assert_eq!((error.line, error.column), (3, 1));
+        assert_eq!(error.token, "adr");
+
+        let error = parse_error("#ip 0\naddi 1 2");
+        assert_eq!(error.kind, ParseErrorKind::MissingOperand);
+        assert_eq!((error.line, error.column), (2, 9));
+
+        let error = parse_error("#ip 0\naddi x 2 3");
+        assert_eq!(error.kind, ParseErrorKind::NonNumericRegister);
+        assert_eq!(
+            (error.line, error.column, error.token.as_str()),
+            (2, 6, "x")
+        );
+
+        let error = parse_error("#ip 0\naddi 1 two 3");
+        assert_eq!(error.kind, ParseErrorKind::NonNumericImmediate);
+
+        let error = parse_error("#ip 0\nseti 1 2 3 4");
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedToken);
+        assert_eq!(error.column, 12);
+
+        let error = parse_error("#ip 9\nseti 1 2 3");
+        assert_eq!(error.kind, ParseErrorKind::BadIpDirective);
+        assert_eq!(
+            (error.line, error.column, error.token.as_str()),
+            (1, 5, "9")
+        );
+
+        assert_eq!(parse_error("").kind, ParseErrorKind::BadIpDirective);
+        assert_eq!(
+            parse_error("\nseti 1 2 3").kind,
+            ParseErrorKind::BadIpDirective
+        );
+    }
+
+    #[test]
+    fn unbound_ip() {
+        let program = "seti 5 0 1\naddi 1 2 3\n";
+        let error = Vm::<usize>::parse_with(program, Config::DAY16).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::BadIpDirective);
+
+        let mut vm = Vm::<usize>::parse_unbound(program, Config::DAY16).unwrap();
+        assert_eq!(vm.ip_index(), 4);
+        assert_eq!(vm.program.len(), 2);
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0, [0, 5, 0, 7, 2]);
+
+        let error = Vm::<usize>::parse_unbound("seti 5 0 4", Config::DAY16).unwrap_err();
+        assert_eq!(
+            (error.line, error.kind),
+            (1, ParseErrorKind::NoSuchRegister)
+        );
+    }
+
+    #[test]
+    fn faults() {
+        let mut vm: Vm = "#ip 0\nseti 1 0 9".parse().unwrap();
+        let fault = vm.step().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::BadRegister(9));
+        assert_eq!(fault.ip, 0);
+        assert_eq!(fault.instruction.unwrap().opcode, Opcode::Seti);
+
+        let mut vm: Vm = "#ip 0\nseti 3 0 0".parse().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.step().unwrap_err().kind, FaultKind::IpOutOfBounds);
+
+        let program = format!("#ip 5\nseti {} 0 1\nmuli 1 2 1", usize::MAX);
+        let mut vm: Vm = program.parse().unwrap();
+        assert_eq!(vm.run().unwrap_err().kind, FaultKind::Overflow);
+        assert_eq!(vm.registers.0[5], 1);
+
+        for &(mode, expected) in &[
+            (ArithmeticMode::Wrapping, usize::MAX - 1),
+            (ArithmeticMode::Saturating, usize::MAX),
+        ] {
+            let mut vm: Vm = program.parse().unwrap();
+            vm.arithmetic = mode;
+            vm.run().unwrap();
+            assert_eq!(vm.registers.0[1], expected);
+        }
+
+        // The registers are public, so they can be replaced by too few to hold the ip.
+        let mut vm: Vm = "#ip 5\nseti 1 0 1".parse().unwrap();
+        vm.registers = Registers(vec![0; 3]);
+        assert_eq!(vm.step().unwrap_err().kind, FaultKind::BadRegister(5));
+        assert_eq!(vm.run().unwrap_err().kind, FaultKind::BadRegister(5));
+        assert_eq!(vm.ip().unwrap_err(), FaultKind::BadRegister(5));
+    }
+
+    #[test]
+    fn run_with_limits() {
+        let program = "#ip 0\nseti 0 0 2\naddi 1 1 1\nseti 0 0 0";
+        let mut vm: Vm = program.parse().unwrap();
+        let stop = vm.run_with(Limits::new().max_steps(5)).unwrap();
+        assert_eq!(stop, StopReason::StepLimit);
+        assert_eq!(vm.registers.0[1], 2);
+
+        let stop = vm.run_with(Limits::new().break_at(1)).unwrap();
+        assert_eq!(stop, StopReason::Breakpoint(1));
+        assert_eq!(vm.registers.0[1], 3);
+
+        let stop = vm
+            .run_with(Limits::new().until(|r| r.0[1] == 10).until(|r| r.0[1] == 7))
             .unwrap();
-        let program = line_iter.map(|line| line.parse().unwrap()).collect();
+        assert_eq!(stop, StopReason::Predicate(1));
 
-        Ok(Vm {
-            registers: Default::default(),
-            ip_index,
-            program,
-        })
+        let stop = vm
+            .run_with(Limits::new().time_budget(Duration::from_millis(0)))
+            .unwrap();
+        assert_eq!(stop, StopReason::TimeLimit);
+
+        let mut vm: Vm = "#ip 0\nseti 5 0 0".parse().unwrap();
+        assert_eq!(vm.run_with(Limits::new()).unwrap(), StopReason::Halted);
+    }
+
+    #[test]
+    fn word_types() {
+        let mut vm: Vm<u32> = "#ip 0\nseti 7 0 1\nmuli 1 3 1".parse().unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[1], 21);
+
+        let mut vm: Vm<u32> = "#ip 0\nseti 65536 0 1\nmulr 1 1 1".parse().unwrap();
+        assert_eq!(vm.run().unwrap_err().kind, FaultKind::Overflow);
+        let mut vm: Vm<u32> = format!("#ip 0\nseti {} 0 1", 1u64 << 32).parse().unwrap();
+        assert_eq!(vm.run().unwrap_err().kind, FaultKind::Overflow);
+
+        let mut vm: Vm<i64> = "#ip 0\ngtrr 2 1 3\nseti 10 0 0".parse().unwrap();
+        vm.registers.0[1] = -1;
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[3], 1);
+
+        vm.registers.0[0] = -5;
+        assert_eq!(vm.run_with(Limits::new()).unwrap(), StopReason::Halted);
+    }
+
+    #[test]
+    fn configs() {
+        let vm = Vm::<usize>::parse_with("#ip 3\nseti 1 0 2", Config::DAY16).unwrap();
+        assert_eq!(vm.registers, Registers(vec![0; 4]));
+
+        let error = Vm::<usize>::parse_with("#ip 0\naddr 1 4 2", Config::DAY16).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NoSuchRegister);
+        assert_eq!(
+            (error.line, error.column, error.token.as_str()),
+            (2, 8, "4")
+        );
+        assert!(Vm::<usize>::parse_with("#ip 0\naddr 1 4 2", Config::DAY19).is_ok());
+        assert!(Vm::<u64>::parse_with("#ip 0\nseti 4 1 2", Config::DAY16).is_ok());
+
+        let error = Vm::<usize>::parse_with("#ip 4\nseti 1 0 2", Config::DAY16).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::BadIpDirective);
+    }
+
+    #[test]
+    fn parse_registers() {
+        let registers: Registers = "3, 2, 1, 1".parse().unwrap();
+        assert_eq!(registers, Registers(vec![3, 2, 1, 1]));
+
+        let error = "3, 2, a, 1".parse::<Registers>().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NonNumericRegister);
+        assert_eq!((error.column, error.token.as_str()), (7, "a"));
     }
 }
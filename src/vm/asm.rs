@@ -0,0 +1,411 @@
+use super::*;
+use std::collections::HashMap;
+
+/// Assembles elfcode written with labels, comments and symbolic names.
+///
+/// On top of the bare `#ip N` format this accepts:
+///
+/// - `;` comments and blank lines,
+/// - `name:` labels, which stand for the ip of the next instruction,
+/// - `#define NAME value` constants and `#alias name register` register names,
+/// - `rN` register names, with `ip` naming the register bound by `#ip`,
+/// - `symbol+N` and `symbol-N` offsets in immediates, e.g. `seti loop-1 0 ip`,
+/// - `jmp label`, shorthand for `seti label-1 0 ip`.
+///
+/// Register operands are checked against the six registers of the machine it builds.
+pub fn assemble(source: &str) -> Result<Vm, ParseError> {
+    let lines: Vec<(usize, &str)> = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split(';').next().unwrap()))
+        .collect();
+
+    let mut symbols = HashMap::new();
+    let mut ip_directive = None;
+    let mut statements = vec![];
+    for &(number, line) in &lines {
+        let at_line = |e: ParseError| e.at_line(number);
+        let mut iter = tokens(line).peekable();
+
+        while let Some(&(column, token)) = iter.peek() {
+            let name = match token.strip_suffix(':') {
+                Some(name) => name,
+                None => break,
+            };
+            define(
+                &mut symbols,
+                name,
+                Symbol::Constant(statements.len()),
+                column,
+            )
+            .map_err(at_line)?;
+            iter.next();
+        }
+
+        let rest: Vec<(usize, &str)> = iter.collect();
+        match rest.first() {
+            None => {}
+            Some(&(column, "#ip")) => {
+                if ip_directive.is_some() {
+                    let e = ParseError::new(ParseErrorKind::BadIpDirective, column, "#ip");
+                    return Err(e.at_line(number));
+                }
+                ip_directive = Some((number, line));
+            }
+            Some(&(_, "#define")) | Some(&(_, "#alias")) => {
+                directive(&mut symbols, &rest, line).map_err(at_line)?;
+            }
+            Some(&(column, token)) if token.starts_with('#') => {
+                let e = ParseError::new(ParseErrorKind::BadDirective, column, token);
+                return Err(e.at_line(number));
+            }
+            Some(_) => statements.push((number, line, rest)),
+        }
+    }
+
+    let registers = Registers::default();
+    let ip_index = match ip_directive {
+        Some((number, line)) => {
+            ip_register(&symbols, line, registers.0.len()).map_err(|e| e.at_line(number))?
+        }
+        None => return Err(ParseError::new(ParseErrorKind::BadIpDirective, 1, "")),
+    };
+    symbols.insert("ip".to_owned(), Symbol::Register(ip_index));
+
+    let program = statements
+        .into_iter()
+        .map(|(number, line, tokens)| {
+            instruction(&symbols, &tokens, line, ip_index, registers.0.len())
+                .map_err(|e| e.at_line(number))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Vm {
+        registers,
+        program,
+        instruction_set: Default::default(),
+        arithmetic: Default::default(),
+        accelerate: true,
+        taps: vec![],
+        ip_index,
+        output: Default::default(),
+        steps: 0,
+        history: History::new(0),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symbol {
+    Constant(usize),
+    Register(usize),
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && register_number(name).is_none()
+        && name != "ip"
+}
+
+fn register_number(name: &str) -> Option<usize> {
+    name.strip_prefix('r').and_then(|n| n.parse().ok())
+}
+
+fn define(
+    symbols: &mut HashMap<String, Symbol>,
+    name: &str,
+    symbol: Symbol,
+    column: usize,
+) -> Result<(), ParseError> {
+    if !is_identifier(name) {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            name,
+        ));
+    }
+    if symbols.insert(name.to_owned(), symbol).is_some() {
+        return Err(ParseError::new(
+            ParseErrorKind::DuplicateSymbol,
+            column,
+            name,
+        ));
+    }
+    Ok(())
+}
+
+fn directive(
+    symbols: &mut HashMap<String, Symbol>,
+    tokens: &[(usize, &str)],
+    line: &str,
+) -> Result<(), ParseError> {
+    let end = line.trim_end().len() + 1;
+    let (column, name) = *tokens
+        .get(1)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::MissingOperand, end, ""))?;
+    let (value_column, value) = *tokens
+        .get(2)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::MissingOperand, end, ""))?;
+    if let Some(&(column, token)) = tokens.get(3) {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            token,
+        ));
+    }
+
+    let symbol = if tokens[0].1 == "#define" {
+        let value = value.parse().map_err(|_| {
+            ParseError::new(ParseErrorKind::NonNumericImmediate, value_column, value)
+        })?;
+        Symbol::Constant(value)
+    } else {
+        let register = value
+            .parse()
+            .ok()
+            .or_else(|| register_number(value))
+            .ok_or_else(|| {
+                ParseError::new(ParseErrorKind::NonNumericRegister, value_column, value)
+            })?;
+        Symbol::Register(register)
+    };
+    define(symbols, name, symbol, column)
+}
+
+fn ip_register(
+    symbols: &HashMap<String, Symbol>,
+    line: &str,
+    register_count: usize,
+) -> Result<usize, ParseError> {
+    let mut iter = tokens(line).skip(1);
+    let bad = |column, token| ParseError::new(ParseErrorKind::BadIpDirective, column, token);
+    let (column, token) = iter
+        .next()
+        .ok_or_else(|| bad(line.trim_end().len() + 1, ""))?;
+    let register = match resolve(symbols, token, Operand::Register) {
+        Ok(register) if register < register_count => register,
+        _ => return Err(bad(column, token)),
+    };
+    if let Some((column, token)) = iter.next() {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            token,
+        ));
+    }
+    Ok(register)
+}
+
+/// Resolves an operand to a number, reporting failures with the token's kind but no position.
+fn resolve(
+    symbols: &HashMap<String, Symbol>,
+    token: &str,
+    kind: Operand,
+) -> Result<usize, ParseErrorKind> {
+    let wrong_kind = if kind == Operand::Register {
+        ParseErrorKind::NonNumericRegister
+    } else {
+        ParseErrorKind::NonNumericImmediate
+    };
+
+    if let Ok(value) = token.parse() {
+        return Ok(value);
+    }
+    if kind == Operand::Register {
+        if let Some(register) = register_number(token) {
+            return Ok(register);
+        }
+    }
+
+    let split = token
+        .char_indices()
+        .skip(1)
+        .filter(|&(_, c)| c == '+' || c == '-')
+        .last();
+    let (name, offset) = match split {
+        Some((i, _)) => {
+            let offset: usize = token[i + 1..].parse().map_err(|_| wrong_kind)?;
+            (&token[..i], Some((&token[i..=i], offset)))
+        }
+        None => (token, None),
+    };
+
+    let value = match symbols.get(name) {
+        Some(&Symbol::Register(register)) if kind == Operand::Register => register,
+        Some(&Symbol::Constant(value)) if kind != Operand::Register => value,
+        Some(_) => return Err(wrong_kind),
+        None if is_identifier(name) || name == "ip" => return Err(ParseErrorKind::UndefinedSymbol),
+        None => return Err(wrong_kind),
+    };
+
+    match offset {
+        None => Ok(value),
+        Some(_) if kind == Operand::Register => Err(wrong_kind),
+        Some(("+", offset)) => value.checked_add(offset).ok_or(ParseErrorKind::OutOfRange),
+        Some((_, offset)) => value.checked_sub(offset).ok_or(ParseErrorKind::OutOfRange),
+    }
+}
+
+fn instruction(
+    symbols: &HashMap<String, Symbol>,
+    tokens: &[(usize, &str)],
+    line: &str,
+    ip_index: usize,
+    register_count: usize,
+) -> Result<Instruction, ParseError> {
+    let end = line.trim_end().len() + 1;
+    let (column, mnemonic) = tokens[0];
+    let mut operands = tokens[1..].iter();
+    let mut operand = |kind| {
+        let &(column, token) = operands
+            .next()
+            .ok_or_else(|| ParseError::new(ParseErrorKind::MissingOperand, end, ""))?;
+        match resolve(symbols, token, kind) {
+            Ok(value) if kind == Operand::Register && value >= register_count => Err(
+                ParseError::new(ParseErrorKind::NoSuchRegister, column, token),
+            ),
+            result => result.map_err(|e| ParseError::new(e, column, token)),
+        }
+    };
+
+    let instruction = if mnemonic == "jmp" {
+        let target = operand(Operand::Immediate)?;
+        let input1 = target
+            .checked_sub(1)
+            .ok_or_else(|| ParseError::new(ParseErrorKind::OutOfRange, tokens[1].0, tokens[1].1))?;
+        Instruction {
+            opcode: Opcode::Seti,
+            input1,
+            input2: 0,
+            output: ip_index,
+        }
+    } else {
+        let opcode: Opcode = mnemonic
+            .parse()
+            .map_err(|_| ParseError::new(ParseErrorKind::UnknownMnemonic, column, mnemonic))?;
+        let (kind1, kind2) = opcode.operands();
+        Instruction {
+            opcode,
+            input1: operand(kind1)?,
+            input2: operand(kind2)?,
+            output: operand(Operand::Register)?,
+        }
+    };
+
+    if let Some(&(column, token)) = operands.next() {
+        return Err(ParseError::new(
+            ParseErrorKind::UnexpectedToken,
+            column,
+            token,
+        ));
+    }
+
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r"
+; Sums 1..=LIMIT into acc.
+#ip 5
+#define LIMIT 10
+#alias acc 0
+#alias i r1
+
+        seti 1 0 i          ; i = 1
+loop:   addr acc i acc
+        addi i 1 i
+        gtri i LIMIT 2
+        addr 2 ip ip        ; if i > LIMIT goto done
+        jmp loop
+done:
+";
+
+    #[test]
+    fn assemble_program() {
+        let mut vm = assemble(SOURCE).unwrap();
+        assert_eq!(vm.ip_index(), 5);
+        assert_eq!(
+            vm.program[4],
+            Instruction {
+                opcode: Opcode::Addr,
+                input1: 2,
+                input2: 5,
+                output: 5,
+            }
+        );
+        assert_eq!(vm.program[5].to_string(), "seti 0 0 5");
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[0], 55);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let error = assemble("#ip 0\nseti missing 0 1").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UndefinedSymbol);
+        assert_eq!((error.line, error.column), (2, 6));
+
+        let error = assemble("#ip 0\nx: seti 0 0 1\nx: seti 0 0 1").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::DuplicateSymbol);
+        assert_eq!(error.line, 3);
+
+        let error = assemble("#ip 0\n#alias a 1\nseti a 0 1").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NonNumericImmediate);
+
+        let error = assemble("start: jmp start\n#ip 0").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::OutOfRange);
+
+        let error = assemble("#ip 0\n#include x").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::BadDirective);
+
+        let error = assemble("seti 0 0 1").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::BadIpDirective);
+
+        let error = assemble("#ip 0\n\naddr 9 0 1").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NoSuchRegister);
+        assert_eq!(
+            (error.line, error.column, error.token.as_str()),
+            (3, 6, "9")
+        );
+
+        let error = assemble("#ip 0\n#alias acc r6\nseti 1 0 acc").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NoSuchRegister);
+        assert_eq!((error.line, error.column), (3, 10));
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = r"#ip 4
+addi 4 16 4
+seti 1 8 1
+seti 1 5 5
+mulr 1 5 3
+eqrr 3 2 3
+addr 3 4 4
+addi 4 1 4
+addr 1 0 0
+addi 5 1 5
+gtrr 5 2 3
+addr 4 3 4
+seti 2 4 4
+addi 1 1 1
+gtrr 1 2 3
+addr 3 4 4
+seti 1 7 4
+mulr 4 4 4
+";
+        let vm: Vm = source.parse().unwrap();
+        let listing = disasm::assembly(&vm);
+        assert!(listing.contains("L3:\n    mulr r1 r5 r3"));
+        assert!(listing.contains("    seti L3-1 4 ip"));
+
+        let assembled = assemble(&listing).unwrap();
+        assert_eq!(assembled.ip_index(), vm.ip_index());
+        assert_eq!(assembled.program, vm.program);
+    }
+}
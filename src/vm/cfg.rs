@@ -0,0 +1,315 @@
+use super::disasm::{self, Flow};
+use super::*;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+    /// Control may leave the program from the end of this block.
+    pub exits: bool,
+    /// The block ends in a jump whose target cannot be determined statically.
+    pub indirect: bool,
+}
+
+impl Block {
+    pub fn contains(&self, ip: usize) -> bool {
+        self.start <= ip && ip <= self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    pub latches: Vec<usize>,
+    pub body: BTreeSet<usize>,
+}
+
+/// Basic blocks of a program and the jumps between them, indexed by block.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+
+impl Cfg {
+    pub fn new<W: Word>(vm: &Vm<W>) -> Cfg {
+        Cfg::build(&vm.program, vm.ip_index)
+    }
+
+    pub fn build(program: &[Instruction], ip_index: usize) -> Cfg {
+        let flows: Vec<Flow> = (0..program.len())
+            .map(|ip| disasm::flow(program, ip_index, ip))
+            .collect();
+
+        let mut leaders = disasm::jump_targets(program, ip_index);
+        leaders.insert(0);
+        leaders.extend(
+            flows
+                .iter()
+                .enumerate()
+                .filter(|(_, flow)| **flow != Flow::Next)
+                .map(|(ip, _)| ip + 1),
+        );
+        let leaders: Vec<usize> = leaders
+            .into_iter()
+            .filter(|&ip| ip < program.len())
+            .collect();
+
+        let block_of = |ip: usize| leaders.iter().rposition(|&leader| leader <= ip);
+        let blocks = leaders
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = leaders.get(i + 1).map_or(program.len(), |&next| next) - 1;
+                let targets = match flows[end] {
+                    Flow::Next => vec![end + 1],
+                    Flow::Goto(target) => vec![target],
+                    Flow::Branch {
+                        taken, fallthrough, ..
+                    } => vec![fallthrough, taken],
+                    Flow::Indirect => vec![],
+                };
+
+                let mut successors = vec![];
+                for &target in &targets {
+                    if let Some(block) = block_of(target).filter(|_| target < program.len()) {
+                        if !successors.contains(&block) {
+                            successors.push(block);
+                        }
+                    }
+                }
+
+                Block {
+                    start,
+                    end,
+                    successors,
+                    exits: targets.iter().any(|&target| target >= program.len()),
+                    indirect: flows[end] == Flow::Indirect,
+                }
+            })
+            .collect();
+
+        Cfg { blocks }
+    }
+
+    pub fn block_of(&self, ip: usize) -> Option<usize> {
+        self.blocks.iter().position(|block| block.contains(ip))
+    }
+
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for &successor in &block.successors {
+                predecessors[successor].push(i);
+            }
+        }
+        predecessors
+    }
+
+    /// Blocks reached only through indirect jumps are treated as extra entry points, so code
+    /// behind a computed jump (such as day19's `addr 4 0 4` dispatch) is still analysed.
+    fn roots(&self) -> Vec<usize> {
+        let mut roots = vec![];
+        let mut reached = vec![false; self.blocks.len()];
+        while let Some(root) = (0..self.blocks.len()).find(|&i| !reached[i]) {
+            roots.push(root);
+            let mut stack = vec![root];
+            while let Some(i) = stack.pop() {
+                if !reached[i] {
+                    reached[i] = true;
+                    stack.extend(&self.blocks[i].successors);
+                }
+            }
+        }
+        roots
+    }
+
+    /// For each block, the set of blocks that dominate it.
+    pub fn dominators(&self) -> Vec<BTreeSet<usize>> {
+        let predecessors = self.predecessors();
+        let roots = self.roots();
+        let all: BTreeSet<usize> = (0..self.blocks.len()).collect();
+        let mut dominators: Vec<BTreeSet<usize>> = (0..self.blocks.len())
+            .map(|i| {
+                if roots.contains(&i) {
+                    Some(i).into_iter().collect()
+                } else {
+                    all.clone()
+                }
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..self.blocks.len()).filter(|i| !roots.contains(i)) {
+                let mut set = predecessors[i]
+                    .iter()
+                    .map(|&p| dominators[p].clone())
+                    .fold(None, |acc: Option<BTreeSet<usize>>, set| match acc {
+                        Some(acc) => Some(acc.intersection(&set).cloned().collect()),
+                        None => Some(set),
+                    })
+                    .unwrap_or_default();
+                set.insert(i);
+
+                if set != dominators[i] {
+                    dominators[i] = set;
+                    changed = true;
+                }
+            }
+        }
+
+        dominators
+    }
+
+    /// Natural loops, one per header, ordered by header.
+    pub fn loops(&self) -> Vec<Loop> {
+        let dominators = self.dominators();
+        let predecessors = self.predecessors();
+        let mut loops: Vec<Loop> = vec![];
+
+        for (latch, block) in self.blocks.iter().enumerate() {
+            for &header in &block.successors {
+                if !dominators[latch].contains(&header) {
+                    continue;
+                }
+
+                let mut body: BTreeSet<usize> = vec![header, latch].into_iter().collect();
+                let mut stack = vec![latch];
+                while let Some(i) = stack.pop() {
+                    if i == header {
+                        continue;
+                    }
+                    for &p in &predecessors[i] {
+                        if body.insert(p) {
+                            stack.push(p);
+                        }
+                    }
+                }
+
+                match loops.iter_mut().find(|l| l.header == header) {
+                    Some(l) => {
+                        l.latches.push(latch);
+                        l.body.extend(body);
+                    }
+                    None => loops.push(Loop {
+                        header,
+                        latches: vec![latch],
+                        body,
+                    }),
+                }
+            }
+        }
+
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+    /// Renders the graph in Graphviz DOT, with each block listed as pseudo-code and loop back
+    /// edges drawn in red.
+    pub fn to_dot<W: Word>(&self, vm: &Vm<W>) -> String {
+        let back_edges: BTreeSet<(usize, usize)> = self
+            .loops()
+            .iter()
+            .flat_map(|l| l.latches.iter().map(move |&latch| (latch, l.header)))
+            .collect();
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for ip in block.start..=block.end {
+                let statement = disasm::statement(&vm.program, vm.ip_index, ip);
+                write!(label, "{:4}  {}\\l", ip, statement.replace('"', "\\\"")).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", i, label).unwrap();
+        }
+
+        let mut has_exit = false;
+        for (i, block) in self.blocks.iter().enumerate() {
+            for &successor in &block.successors {
+                let style = if back_edges.contains(&(i, successor)) {
+                    " [color=red]"
+                } else {
+                    ""
+                };
+                writeln!(dot, "    b{} -> b{}{};", i, successor, style).unwrap();
+            }
+            if block.exits {
+                writeln!(dot, "    b{} -> exit;", i).unwrap();
+                has_exit = true;
+            }
+            if block.indirect {
+                writeln!(dot, "    b{} -> indirect_{} [style=dashed];", i, i).unwrap();
+                writeln!(dot, "    indirect_{} [shape=point];", i).unwrap();
+            }
+        }
+        if has_exit {
+            writeln!(dot, "    exit [shape=doublecircle];").unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY19;
+
+    #[test]
+    fn blocks() {
+        let vm: Vm = DAY19.parse().unwrap();
+        let cfg = Cfg::new(&vm);
+        let ranges: Vec<(usize, usize)> = cfg.blocks.iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 0),
+                (1, 1),
+                (2, 2),
+                (3, 5),
+                (6, 6),
+                (7, 7),
+                (8, 10),
+                (11, 11),
+                (12, 14),
+                (15, 15),
+                (16, 16),
+                (17, 25),
+                (26, 26),
+                (27, 35),
+            ]
+        );
+        assert_eq!(cfg.blocks[3].successors, vec![4, 5]);
+        assert!(cfg.blocks[10].exits);
+        assert!(cfg.blocks[11].indirect);
+        assert_eq!(cfg.block_of(9), Some(6));
+    }
+
+    #[test]
+    fn loops() {
+        let vm: Vm = DAY19.parse().unwrap();
+        let cfg = Cfg::new(&vm);
+        let loops = cfg.loops();
+        assert_eq!(loops.len(), 2);
+
+        assert_eq!(loops[0].header, 2);
+        assert_eq!(loops[0].latches, vec![9]);
+        assert_eq!(loops[0].body, (2..=9).collect());
+
+        assert_eq!(loops[1].header, 3);
+        assert_eq!(loops[1].latches, vec![7]);
+        assert_eq!(loops[1].body, (3..=7).collect());
+
+        let dot = cfg.to_dot(&vm);
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b7 -> b3 [color=red];\n"));
+        assert!(dot.contains("    b10 -> exit;\n"));
+    }
+}
@@ -0,0 +1,426 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    AddRR,
+    AddRI,
+    MulRR,
+    MulRI,
+    AndRR,
+    AndRI,
+    OrRR,
+    OrRI,
+    SetR,
+    SetI,
+    GtIR,
+    GtRI,
+    GtRR,
+    EqIR,
+    EqRI,
+    EqRR,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Compute {
+        kind: Kind,
+        a: usize,
+        b: usize,
+        output: usize,
+    },
+    /// Writes the ip register with a value known only at run time.
+    Jump {
+        kind: Kind,
+        a: usize,
+        b: usize,
+    },
+    /// Continues at a constant ip, already incremented.
+    Goto(usize),
+    /// A comparison fused with the `addr` that branches on it.
+    Branch {
+        kind: Kind,
+        a: usize,
+        b: usize,
+        output: usize,
+    },
+    /// Computes a value for a register that does not exist, which faults after any overflow.
+    BadOutput {
+        kind: Kind,
+        a: usize,
+        b: usize,
+        output: usize,
+    },
+    Fault(FaultKind),
+}
+
+#[inline(always)]
+fn compute<const N: usize>(
+    kind: Kind,
+    a: usize,
+    b: usize,
+    registers: &[usize; N],
+    mode: ArithmeticMode,
+) -> Result<usize, FaultKind> {
+    use self::Kind::*;
+    let value = match kind {
+        AddRR => mode.add(registers[a], registers[b])?,
+        AddRI => mode.add(registers[a], b)?,
+        MulRR => mode.mul(registers[a], registers[b])?,
+        MulRI => mode.mul(registers[a], b)?,
+        AndRR => registers[a] & registers[b],
+        AndRI => registers[a] & b,
+        OrRR => registers[a] | registers[b],
+        OrRI => registers[a] | b,
+        SetR => registers[a],
+        SetI => a,
+        GtIR => (a > registers[b]) as usize,
+        GtRI => (registers[a] > b) as usize,
+        GtRR => (registers[a] > registers[b]) as usize,
+        EqIR => (a == registers[b]) as usize,
+        EqRI => (registers[a] == b) as usize,
+        EqRR => (registers[a] == registers[b]) as usize,
+    };
+    Ok(value)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Input {
+    Register(usize),
+    Immediate(usize),
+}
+
+/// Picks the specialised kind for an instruction once reads of the ip register have been
+/// replaced by the constant ip. `None` means both inputs are constant.
+fn specialise(opcode: &Opcode, a: Input, b: Input) -> Option<(Kind, usize, usize)> {
+    use self::Input::*;
+    use self::Kind::*;
+    use self::Opcode::*;
+
+    let commutative = |rr, ri| match (a, b) {
+        (Register(a), Register(b)) => Some((rr, a, b)),
+        (Register(r), Immediate(i)) | (Immediate(i), Register(r)) => Some((ri, r, i)),
+        (Immediate(_), Immediate(_)) => None,
+    };
+    let ordered = |ir, ri, rr| match (a, b) {
+        (Immediate(a), Register(b)) => Some((ir, a, b)),
+        (Register(a), Immediate(b)) => Some((ri, a, b)),
+        (Register(a), Register(b)) => Some((rr, a, b)),
+        (Immediate(_), Immediate(_)) => None,
+    };
+
+    match opcode {
+        Addr | Addi => commutative(AddRR, AddRI),
+        Mulr | Muli => commutative(MulRR, MulRI),
+        Banr | Bani => commutative(AndRR, AndRI),
+        Borr | Bori => commutative(OrRR, OrRI),
+        Setr | Seti => match a {
+            Register(a) => Some((SetR, a, 0)),
+            Immediate(_) => None,
+        },
+        Gtir | Gtri | Gtrr => ordered(GtIR, GtRI, GtRR),
+        Eqir | Eqri | Eqrr => ordered(EqIR, EqRI, EqRR),
+        Custom(_) => None,
+    }
+}
+
+/// An elfcode program translated for a machine with exactly `N` registers.
+///
+/// Operands are decoded once, reads of the ip register become constants, writes to it become
+/// jumps, and a comparison followed by the `addr` that branches on it runs as a single op.
+/// Idioms are not accelerated, taps are not supported and running clears the VM's history.
+#[derive(Debug, Clone)]
+pub struct Compiled<const N: usize> {
+    ops: Vec<Op>,
+    program: Vec<Instruction>,
+    ip_index: usize,
+    mode: ArithmeticMode,
+}
+
+impl<const N: usize> Compiled<N> {
+    /// Returns `None` if the VM's register file is not `N` registers wide, the program uses
+    /// custom opcodes or the VM has taps.
+    pub fn new(vm: &Vm) -> Option<Compiled<N>> {
+        if vm.registers.0.len() != N || vm.ip_index >= N || !vm.taps.is_empty() {
+            return None;
+        }
+        let custom = |i: &Instruction| matches!(i.opcode, Opcode::Custom(_));
+        if vm.program.iter().any(custom) {
+            return None;
+        }
+
+        let program = &vm.program;
+        let ops = (0..program.len())
+            .map(|ip| Compiled::<N>::compile(program, vm.ip_index, vm.arithmetic, ip))
+            .collect();
+
+        Some(Compiled {
+            ops,
+            program: program.clone(),
+            ip_index: vm.ip_index,
+            mode: vm.arithmetic,
+        })
+    }
+
+    fn compile(program: &[Instruction], ip_index: usize, mode: ArithmeticMode, ip: usize) -> Op {
+        let instruction = &program[ip];
+        let (kind1, kind2) = instruction.opcode.operands();
+        let input = |kind, value| match kind {
+            Operand::Register if value == ip_index => Ok(Input::Immediate(ip)),
+            Operand::Register if value < N => Ok(Input::Register(value)),
+            Operand::Register => Err(FaultKind::BadRegister(value)),
+            _ => Ok(Input::Immediate(value)),
+        };
+        let inputs = input(kind1, instruction.input1)
+            .and_then(|a| input(kind2, instruction.input2).map(|b| (a, b)));
+        let (a, b) = match inputs {
+            Ok(inputs) => inputs,
+            Err(kind) => return Op::Fault(kind),
+        };
+
+        let (kind, a, b) = match specialise(&instruction.opcode, a, b) {
+            Some(specialised) => specialised,
+            None => {
+                let mut registers = Registers(vec![0; N.max(instruction.output + 1)]);
+                registers.0[ip_index] = ip;
+                let value = instruction
+                    .execute_with(&mut registers, mode)
+                    .map(|_| registers.0[instruction.output]);
+                match value {
+                    Ok(_) if instruction.output >= N => {
+                        return Op::Fault(FaultKind::BadRegister(instruction.output));
+                    }
+                    Ok(value) if instruction.output != ip_index => {
+                        return Op::Compute {
+                            kind: Kind::SetI,
+                            a: value,
+                            b: 0,
+                            output: instruction.output,
+                        };
+                    }
+                    Ok(value) => match mode.add(value, 1) {
+                        Ok(target) => return Op::Goto(target),
+                        Err(_) => (Kind::SetI, value, 0),
+                    },
+                    Err(kind) => return Op::Fault(kind),
+                }
+            }
+        };
+
+        if instruction.output == ip_index {
+            return Op::Jump { kind, a, b };
+        }
+        if instruction.output >= N {
+            return Op::BadOutput {
+                kind,
+                a,
+                b,
+                output: instruction.output,
+            };
+        }
+
+        let branches = ip + 1 < program.len()
+            && match disasm::flow(program, ip_index, ip + 1) {
+                disasm::Flow::Branch { condition, .. } => condition == instruction.output,
+                _ => false,
+            };
+        if branches {
+            Op::Branch {
+                kind,
+                a,
+                b,
+                output: instruction.output,
+            }
+        } else {
+            Op::Compute {
+                kind,
+                a,
+                b,
+                output: instruction.output,
+            }
+        }
+    }
+
+    pub fn run(&self, vm: &mut Vm) -> Result<StopReason, Fault> {
+        self.run_with(vm, Limits::new())
+    }
+
+    /// Runs with the same semantics as `Vm::run_with`, except that recognised idioms are
+    /// interpreted rather than accelerated. Predicates are supported but force a copy of the
+    /// register file after every step, and observers fall back to the interpreter.
+    pub fn run_with(&self, vm: &mut Vm, mut limits: Limits) -> Result<StopReason, Fault> {
+        if !limits.observers.is_empty() {
+            return vm.run_with(limits);
+        }
+
+        let mut registers = [0; N];
+        let ip = vm.current_ip()?;
+        if vm.registers.0.len() != N {
+            return Err(Fault {
+                kind: FaultKind::BadRegister(N.min(vm.registers.0.len())),
+                ip,
+                instruction: None,
+            });
+        }
+        registers.copy_from_slice(&vm.registers.0);
+
+        let len = self.ops.len();
+        let mut is_breakpoint = vec![false; len + 1];
+        for &ip in limits.breakpoints.iter().filter(|&&ip| ip < len) {
+            is_breakpoint[ip] = true;
+        }
+        let max_steps = limits.max_steps.unwrap_or(usize::MAX);
+        let start = Instant::now();
+        let mut next_clock = 0;
+        let mut executed = 0;
+        let mut ip = registers[self.ip_index];
+        let fuse = limits.predicates.is_empty();
+
+        let result = loop {
+            if ip >= len {
+                break Ok(StopReason::Halted);
+            }
+            if executed >= max_steps {
+                break Ok(StopReason::StepLimit);
+            }
+            if let Some(budget) = limits.time_budget {
+                if executed >= next_clock {
+                    next_clock = executed + CLOCK_INTERVAL;
+                    if start.elapsed() >= budget {
+                        break Ok(StopReason::TimeLimit);
+                    }
+                }
+            }
+
+            let fault = |kind| Fault {
+                kind,
+                ip,
+                instruction: Some(self.program[ip].clone()),
+            };
+            match self.ops[ip] {
+                Op::Compute { kind, a, b, output } => {
+                    match compute(kind, a, b, &registers, self.mode) {
+                        Ok(value) => registers[output] = value,
+                        Err(kind) => break Err(fault(kind)),
+                    }
+                    ip += 1;
+                    executed += 1;
+                }
+                Op::Branch { kind, a, b, output } => {
+                    let condition = compute(kind, a, b, &registers, self.mode).unwrap();
+                    registers[output] = condition;
+                    if fuse && executed + 1 < max_steps && !is_breakpoint[ip + 1] {
+                        ip += 2 + condition;
+                        executed += 2;
+                    } else {
+                        ip += 1;
+                        executed += 1;
+                    }
+                }
+                Op::Jump { kind, a, b } => {
+                    let value = match compute(kind, a, b, &registers, self.mode) {
+                        Ok(value) => value,
+                        Err(kind) => break Err(fault(kind)),
+                    };
+                    match self.mode.add(value, 1) {
+                        Ok(next) => ip = next,
+                        Err(kind) => {
+                            let fault = fault(kind);
+                            ip = value;
+                            break Err(fault);
+                        }
+                    }
+                    executed += 1;
+                }
+                Op::Goto(target) => {
+                    ip = target;
+                    executed += 1;
+                }
+                Op::BadOutput { kind, a, b, output } => {
+                    let kind = compute(kind, a, b, &registers, self.mode)
+                        .err()
+                        .unwrap_or(FaultKind::BadRegister(output));
+                    break Err(fault(kind));
+                }
+                Op::Fault(kind) => break Err(fault(kind)),
+            }
+
+            if is_breakpoint[ip.min(len)] || (ip >= len && limits.breakpoints.contains(&ip)) {
+                break Ok(StopReason::Breakpoint(ip));
+            }
+            if !limits.predicates.is_empty() {
+                registers[self.ip_index] = ip;
+                let snapshot = Registers(registers.to_vec());
+                if let Some(index) = limits
+                    .predicates
+                    .iter_mut()
+                    .position(|predicate| predicate(&snapshot))
+                {
+                    break Ok(StopReason::Predicate(index));
+                }
+            }
+        };
+
+        registers[self.ip_index] = ip;
+        vm.registers.0.copy_from_slice(&registers);
+        vm.steps += executed;
+        vm.history.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY21;
+
+    fn interpreter() -> Vm {
+        let mut vm: Vm = DAY21.parse().unwrap();
+        vm.accelerate = false;
+        vm
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        let mut expected = interpreter();
+        let mut actual = interpreter();
+        let compiled = Compiled::<6>::new(&actual).unwrap();
+
+        for _ in 0..10 {
+            let limits = || Limits::new().break_at(28).break_at(21);
+            assert_eq!(
+                compiled.run_with(&mut actual, limits()).unwrap(),
+                expected.run_with(limits()).unwrap()
+            );
+            assert_eq!(actual.registers, expected.registers);
+        }
+
+        for &steps in &[1, 2, 3, 1000, 1001] {
+            let limits = || Limits::new().max_steps(steps);
+            assert_eq!(
+                compiled.run_with(&mut actual, limits()).unwrap(),
+                expected.run_with(limits()).unwrap()
+            );
+            assert_eq!(actual.registers, expected.registers);
+        }
+
+        let limits = || Limits::new().until(|r| r.0[4] > 60000);
+        assert_eq!(
+            compiled.run_with(&mut actual, limits()).unwrap(),
+            StopReason::Predicate(0)
+        );
+        expected.run_with(limits()).unwrap();
+        assert_eq!(actual.registers, expected.registers);
+    }
+
+    #[test]
+    fn faults() {
+        let mut vm: Vm = "#ip 0\naddi 0 1 0\nseti 0 0 0\nmuli 2 3 9".parse().unwrap();
+        let compiled = Compiled::<6>::new(&vm).unwrap();
+        let fault = compiled.run(&mut vm).unwrap_err();
+        assert_eq!(fault.kind, FaultKind::BadRegister(9));
+        assert_eq!(fault.ip, 2);
+        assert_eq!(vm.registers.0[0], 2);
+
+        assert!(Compiled::<4>::new(&vm).is_none());
+    }
+}
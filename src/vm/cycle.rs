@@ -0,0 +1,199 @@
+use super::*;
+
+/// How the machine's state repeats across the times an instruction is about to execute, and
+/// the values one register holds at those times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle<W = usize> {
+    /// Index of the first visit whose state recurs.
+    pub start: usize,
+    /// Visits between two occurrences of the same state.
+    pub length: usize,
+    /// The value at the first visit.
+    pub first: W,
+    /// The value at the last visit before the values start repeating on their own period.
+    /// When the watched register alone decides the next visit's value, as in day21, this is
+    /// the last value not seen at an earlier visit.
+    pub last: W,
+}
+
+impl<W: Word> Vm<W> {
+    /// Watches the registers each time the instruction at `ip` is about to execute, until they
+    /// repeat. Returns `None` if the program halts first.
+    ///
+    /// This uses Brent's algorithm, then finds the values' own period among the divisors of
+    /// the cycle length, so only a handful of states are kept however long the sequence is.
+    /// The price is running the program several times over. The VM itself is left untouched.
+    pub fn find_cycle(&self, ip: usize, register: usize) -> Result<Option<Cycle<W>>, Fault> {
+        let mut vm = self.clone();
+        vm.taps.clear();
+        vm.record_history(0);
+
+        let x0 = if vm.current_ip()? == ip {
+            vm.registers.clone()
+        } else {
+            match visit(&mut vm, &self.registers, ip)? {
+                Some(state) => state,
+                None => return Ok(None),
+            }
+        };
+        let value = |state: &Registers<W>| {
+            state.read(register).map_err(|kind| Fault {
+                kind,
+                ip,
+                instruction: self.program.get(ip).cloned(),
+            })
+        };
+        let first = value(&x0)?;
+
+        let mut next = |state: &Registers<W>| visit(&mut vm, state, ip);
+        macro_rules! next {
+            ($state:expr) => {
+                match next(&$state)? {
+                    Some(state) => state,
+                    None => return Ok(None),
+                }
+            };
+        }
+
+        // Find the cycle length by letting the hare run ahead in powers of two.
+        let (mut power, mut length) = (1, 1);
+        let mut tortoise = x0.clone();
+        let mut hare = next!(x0);
+        while tortoise != hare {
+            if power == length {
+                tortoise = hare.clone();
+                power *= 2;
+                length = 0;
+            }
+            hare = next!(hare);
+            length += 1;
+        }
+
+        // Then the start, by running two pointers `length` apart until they meet.
+        let mut tortoise = x0.clone();
+        let mut hare = x0.clone();
+        for _ in 0..length {
+            hare = next!(hare);
+        }
+        let mut start = 0;
+        while tortoise != hare {
+            tortoise = next!(tortoise);
+            hare = next!(hare);
+            start += 1;
+        }
+
+        // The values repeat with a period dividing the state's, from at most `start` on. Find
+        // the shortest such period by checking the divisors of `length` in turn.
+        let mut period = length;
+        for divisor in (1..length).filter(|divisor| length % divisor == 0) {
+            let mut a = x0.clone();
+            for _ in 0..start {
+                a = next!(a);
+            }
+            let mut b = a.clone();
+            for _ in 0..divisor {
+                b = next!(b);
+            }
+            let mut periodic = true;
+            for _ in 0..length {
+                if value(&a)? != value(&b)? {
+                    periodic = false;
+                    break;
+                }
+                a = next!(a);
+                b = next!(b);
+            }
+            if periodic {
+                period = divisor;
+                break;
+            }
+        }
+
+        // Then walk two pointers `period` apart up to `start`. The values repeat from just
+        // after the last place they differ, and the value before that is the last new one.
+        let mut a = x0.clone();
+        let mut b = x0;
+        for _ in 1..period {
+            b = next!(b);
+        }
+        let mut last = value(&b)?;
+        b = next!(b);
+        for _ in 0..start {
+            let value_b = value(&b)?;
+            if value(&a)? != value_b {
+                last = value_b;
+            }
+            a = next!(a);
+            b = next!(b);
+        }
+
+        Ok(Some(Cycle {
+            start,
+            length,
+            first,
+            last,
+        }))
+    }
+}
+
+/// Runs from `state` until `ip` is about to execute again, or `None` if the program halts.
+fn visit<W: Word>(
+    vm: &mut Vm<W>,
+    state: &Registers<W>,
+    ip: usize,
+) -> Result<Option<Registers<W>>, Fault> {
+    vm.registers = state.clone();
+    match vm.run_with(Limits::new().break_at(ip))? {
+        StopReason::Breakpoint(_) => Ok(Some(vm.registers.clone())),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // At ip 1, r1 runs through a period of 16 and r3 through 0, 1, 3, 7, 7, ..., so the
+    // state repeats from the fourth visit on. The program halts once r1 equals r0.
+    const PROGRAM: &str = r"#ip 5
+seti 1 0 1
+eqrr 1 0 2
+addr 2 5 5
+seti 4 0 5
+seti 99 0 5
+muli 1 5 1
+addi 1 3 1
+bani 1 15 1
+muli 3 2 3
+addi 3 1 3
+bani 3 7 3
+seti 0 0 5
+";
+
+    #[test]
+    fn find_cycle() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        vm.registers.0[0] = 99;
+        let cycle = vm.find_cycle(1, 1).unwrap().unwrap();
+        assert_eq!(
+            cycle,
+            Cycle {
+                start: 3,
+                length: 16,
+                first: 1,
+                // r1 repeats from visit 0 with period 16 while r3 is still settling, so the
+                // last new value is 6 at visit 15, not the 11 at visit 18 before the state
+                // repeats.
+                last: 6,
+            }
+        );
+        assert_eq!(vm.steps(), 0);
+
+        vm.registers.0[0] = 7;
+        assert_eq!(vm.find_cycle(1, 1).unwrap(), None);
+        assert_eq!(
+            vm.find_cycle(1, 9).unwrap_err().kind,
+            FaultKind::BadRegister(9)
+        );
+    }
+}
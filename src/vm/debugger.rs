@@ -0,0 +1,329 @@
+use super::*;
+use std::collections::BTreeSet;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Halted,
+    Paused(usize),
+    Breakpoint(usize),
+    Watchpoint {
+        register: usize,
+        old: usize,
+        new: usize,
+    },
+    /// Stepping backwards ran out of recorded history.
+    OldestRecorded(usize),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Halted => write!(f, "halted"),
+            Event::Paused(ip) => write!(f, "paused at ip {}", ip),
+            Event::Breakpoint(ip) => write!(f, "breakpoint at ip {}", ip),
+            Event::Watchpoint { register, old, new } => {
+                write!(f, "watchpoint r{}: {} -> {}", register, old, new)
+            }
+            Event::OldestRecorded(ip) => write!(f, "reached oldest recorded step at ip {}", ip),
+        }
+    }
+}
+
+/// How many steps the debugger can undo by default.
+pub const HISTORY_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    pub vm: Vm,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    pub fn new(vm: Vm) -> Debugger {
+        Debugger::with_history(vm, HISTORY_SIZE)
+    }
+
+    /// A debugger that can undo up to `steps` steps.
+    pub fn with_history(mut vm: Vm, steps: usize) -> Debugger {
+        // Stepping and watchpoints must see every instruction, not whole idioms at once.
+        vm.accelerate = false;
+        vm.record_history(steps);
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &BTreeSet<usize> {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.insert(ip)
+    }
+
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.remove(&ip)
+    }
+
+    pub fn add_watchpoint(&mut self, register: usize) -> Result<bool, FaultKind> {
+        self.vm.registers.read(register)?;
+        Ok(self.watchpoints.insert(register))
+    }
+
+    pub fn remove_watchpoint(&mut self, register: usize) -> bool {
+        self.watchpoints.remove(&register)
+    }
+
+    pub fn set_register(&mut self, register: usize, value: usize) -> Result<(), FaultKind> {
+        self.vm.registers.write(register, value)
+    }
+
+    pub fn step(&mut self, steps: usize) -> Result<Event, Fault> {
+        self.run(Some(steps), None)
+    }
+
+    pub fn resume(&mut self, max_steps: Option<usize>) -> Result<Event, Fault> {
+        self.run(max_steps, None)
+    }
+
+    pub fn step_back(&mut self, steps: usize) -> Result<Event, Fault> {
+        for _ in 0..steps {
+            if !self.vm.step_back() {
+                return Ok(Event::OldestRecorded(self.vm.current_ip()?));
+            }
+        }
+        Ok(Event::Paused(self.vm.current_ip()?))
+    }
+
+    pub fn rewind_to(&mut self, ip: usize) -> Result<Event, Fault> {
+        if self.vm.run_back_to(ip) {
+            Ok(Event::Paused(ip))
+        } else {
+            Ok(Event::OldestRecorded(self.vm.current_ip()?))
+        }
+    }
+
+    /// Runs until the ip moves past the current instruction, so a backward jump at the end of a
+    /// loop body is stepped over together with every remaining iteration of the loop.
+    pub fn step_over_loop(&mut self) -> Result<Event, Fault> {
+        let ip = self.vm.current_ip()?;
+        self.run(None, Some(ip))
+    }
+
+    fn run(&mut self, max_steps: Option<usize>, past_ip: Option<usize>) -> Result<Event, Fault> {
+        let Debugger {
+            vm,
+            breakpoints,
+            watchpoints,
+        } = self;
+        let ip_index = vm.ip_index;
+        let watched: Vec<(usize, usize)> = watchpoints
+            .iter()
+            .map(|&register| (register, vm.registers.0[register]))
+            .collect();
+
+        let mut limits = Limits::new()
+            .until(|registers| watched.iter().any(|&(i, old)| registers.0[i] != old))
+            .until(|registers| {
+                past_ip.is_some_and(|ip| registers.0.get(ip_index).is_some_and(|&now| now > ip))
+            });
+        if let Some(steps) = max_steps {
+            limits = limits.max_steps(steps);
+        }
+        for &ip in breakpoints.iter() {
+            limits = limits.break_at(ip);
+        }
+
+        let event = match vm.run_with(limits)? {
+            StopReason::Halted => Event::Halted,
+            StopReason::Breakpoint(ip) => Event::Breakpoint(ip),
+            StopReason::Predicate(0) => {
+                let (register, old) = watched
+                    .into_iter()
+                    .find(|&(i, old)| vm.registers.0[i] != old)
+                    .unwrap();
+                Event::Watchpoint {
+                    register,
+                    old,
+                    new: vm.registers.0[register],
+                }
+            }
+            _ => Event::Paused(vm.current_ip()?),
+        };
+
+        Ok(event)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(usize),
+    Continue(Option<usize>),
+    Until,
+    Back(usize),
+    Rewind(usize),
+    Break(usize),
+    Delete(usize),
+    Watch(usize),
+    Unwatch(usize),
+    Set(usize, usize),
+    Jump(usize),
+    Registers,
+    List,
+    Save(String),
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+step [n]         execute n instructions (default 1)
+continue [n]     run until halt, breakpoint or watchpoint, at most n instructions
+until            run until the ip moves past the current instruction
+back [n]         undo n instructions (default 1)
+rewind <ip>      undo instructions until ip is about to execute again
+break <ip>       set a breakpoint
+delete <ip>      remove a breakpoint
+watch <reg>      stop when a register changes
+unwatch <reg>    remove a watchpoint
+set <reg> <val>  write a register
+jump <ip>        move the instruction pointer
+registers        show registers
+list             show the program
+save <file>      write a snapshot that elfdbg can resume from
+help             show this message
+quit             exit";
+
+impl FromStr for Command {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut iter = s.split_whitespace();
+        let name = iter.next().ok_or("empty command")?;
+        if name == "save" {
+            let path = iter.next().ok_or("usage: save <file>")?;
+            return Ok(Command::Save(path.to_owned()));
+        }
+        let args = iter
+            .map(|arg| arg.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        let command = match (name, args.as_slice()) {
+            ("s", []) | ("step", []) => Command::Step(1),
+            ("s", &[n]) | ("step", &[n]) => Command::Step(n),
+            ("c", []) | ("continue", []) => Command::Continue(None),
+            ("c", &[n]) | ("continue", &[n]) => Command::Continue(Some(n)),
+            ("u", []) | ("until", []) => Command::Until,
+            ("back", []) => Command::Back(1),
+            ("back", &[n]) => Command::Back(n),
+            ("rewind", &[ip]) => Command::Rewind(ip),
+            ("b", &[ip]) | ("break", &[ip]) => Command::Break(ip),
+            ("d", &[ip]) | ("delete", &[ip]) => Command::Delete(ip),
+            ("w", &[register]) | ("watch", &[register]) => Command::Watch(register),
+            ("unwatch", &[register]) => Command::Unwatch(register),
+            ("set", &[register, value]) => Command::Set(register, value),
+            ("j", &[ip]) | ("jump", &[ip]) => Command::Jump(ip),
+            ("r", []) | ("registers", []) => Command::Registers,
+            ("l", []) | ("list", []) => Command::List,
+            ("h", []) | ("help", []) => Command::Help,
+            ("q", []) | ("quit", []) => Command::Quit,
+            _ => return Err(Box::from(format!("unknown command `{}`", s.trim()))),
+        };
+
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY19;
+
+    const PROGRAM: &str = r"#ip 0
+seti 0 0 2
+addi 1 1 1
+gtri 1 4 3
+addr 0 3 0
+seti 0 0 0
+seti 7 0 4
+";
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut debugger = Debugger::new(PROGRAM.parse().unwrap());
+        debugger.add_breakpoint(5);
+        assert_eq!(debugger.step(2).unwrap(), Event::Paused(2));
+        assert_eq!(debugger.resume(None).unwrap(), Event::Breakpoint(5));
+        assert_eq!(debugger.vm.registers.0[1], 5);
+
+        debugger.add_watchpoint(4).unwrap();
+        assert_eq!(
+            debugger.resume(None).unwrap(),
+            Event::Watchpoint {
+                register: 4,
+                old: 0,
+                new: 7
+            }
+        );
+        assert_eq!(debugger.resume(None).unwrap(), Event::Halted);
+        assert!(debugger.add_watchpoint(6).is_err());
+    }
+
+    #[test]
+    fn step_over_loop() {
+        let mut debugger = Debugger::new(PROGRAM.parse().unwrap());
+        debugger.step(5).unwrap();
+        assert_eq!(*debugger.vm.ip().unwrap(), 1);
+        debugger.set_register(1, 2).unwrap();
+        assert_eq!(debugger.step_over_loop().unwrap(), Event::Paused(2));
+        debugger.step(2).unwrap();
+        assert_eq!(debugger.step_over_loop().unwrap(), Event::Paused(5));
+        assert_eq!(debugger.vm.registers.0[1], 5);
+    }
+
+    #[test]
+    fn step_back() {
+        let mut debugger = Debugger::new(PROGRAM.parse().unwrap());
+        debugger.add_breakpoint(5);
+        debugger.resume(None).unwrap();
+        assert_eq!(debugger.step_back(1).unwrap(), Event::Paused(3));
+        assert_eq!(debugger.rewind_to(1).unwrap(), Event::Paused(1));
+        assert_eq!(debugger.vm.registers.0[1], 4);
+        assert_eq!(debugger.step_back(100).unwrap(), Event::OldestRecorded(0));
+        assert_eq!(debugger.vm.registers, Registers::default());
+    }
+
+    #[test]
+    fn steps_through_idioms() {
+        // Starting at day19's divisor sum loop, which would otherwise run as one step.
+        let mut vm: Vm = DAY19.parse().unwrap();
+        vm.registers = Registers(vec![0, 0, 36, 0, 1, 0]);
+        let mut debugger = Debugger::new(vm);
+        assert_eq!(debugger.step(1).unwrap(), Event::Paused(2));
+        assert_eq!(debugger.vm.registers.0[0], 0);
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!("s".parse::<Command>().unwrap(), Command::Step(1));
+        assert_eq!("step 10".parse::<Command>().unwrap(), Command::Step(10));
+        assert_eq!("set 2 7".parse::<Command>().unwrap(), Command::Set(2, 7));
+        assert_eq!("c".parse::<Command>().unwrap(), Command::Continue(None));
+        assert!("break".parse::<Command>().is_err());
+        assert!("set x 1".parse::<Command>().is_err());
+        assert_eq!("back".parse::<Command>().unwrap(), Command::Back(1));
+        assert_eq!("rewind 3".parse::<Command>().unwrap(), Command::Rewind(3));
+        assert_eq!(
+            "save run.snap".parse::<Command>().unwrap(),
+            Command::Save("run.snap".to_owned())
+        );
+        assert!("save".parse::<Command>().is_err());
+    }
+}
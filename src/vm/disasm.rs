@@ -0,0 +1,371 @@
+use super::*;
+use std::collections::BTreeSet;
+
+/// Where control goes after an instruction, as far as it can be decided statically.
+///
+/// Targets may lie outside the program, which means the program halts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    Goto(usize),
+    /// Jumps to `taken` when `condition` holds the 1 written by the comparison before it.
+    Branch {
+        condition: usize,
+        taken: usize,
+        fallthrough: usize,
+    },
+    Indirect,
+}
+
+fn is_comparison(opcode: &Opcode) -> bool {
+    use self::Opcode::*;
+    matches!(opcode, Gtir | Gtri | Gtrr | Eqir | Eqri | Eqrr)
+}
+
+/// Evaluates an instruction whose only register input, if any, is the ip register.
+fn evaluate_constant(instruction: &Instruction, ip_index: usize, ip: usize) -> Option<usize> {
+    let (kind1, kind2) = instruction.opcode.operands();
+    let reads_other = |kind, value| kind == Operand::Register && value != ip_index;
+    if reads_other(kind1, instruction.input1) || reads_other(kind2, instruction.input2) {
+        return None;
+    }
+
+    let mut registers = Registers(vec![0; ip_index.max(instruction.output) + 1]);
+    registers.0[ip_index] = ip;
+    instruction
+        .execute_with(&mut registers, ArithmeticMode::Wrapping)
+        .ok()?;
+    Some(registers.0[instruction.output])
+}
+
+pub fn flow(program: &[Instruction], ip_index: usize, ip: usize) -> Flow {
+    use self::Opcode::*;
+    let instruction = &program[ip];
+    if instruction.output != ip_index {
+        return Flow::Next;
+    }
+
+    if let Some(target) = evaluate_constant(instruction, ip_index, ip) {
+        return Flow::Goto(target.wrapping_add(1));
+    }
+
+    if instruction.opcode == Addr {
+        let condition = if instruction.input1 == ip_index {
+            instruction.input2
+        } else if instruction.input2 == ip_index {
+            instruction.input1
+        } else {
+            return Flow::Indirect;
+        };
+        let is_boolean = ip > 0 && {
+            let previous = &program[ip - 1];
+            is_comparison(&previous.opcode) && previous.output == condition
+        };
+        if is_boolean {
+            return Flow::Branch {
+                condition,
+                taken: ip + 2,
+                fallthrough: ip + 1,
+            };
+        }
+    }
+
+    Flow::Indirect
+}
+
+/// Every statically known jump target inside the program.
+pub fn jump_targets(program: &[Instruction], ip_index: usize) -> BTreeSet<usize> {
+    (0..program.len())
+        .flat_map(|ip| match flow(program, ip_index, ip) {
+            Flow::Goto(target) => vec![target],
+            Flow::Branch { taken, .. } => vec![taken],
+            _ => vec![],
+        })
+        .filter(|&target| target < program.len())
+        .collect()
+}
+
+pub fn label(target: usize, program_len: usize) -> String {
+    if target < program_len {
+        format!("L{}", target)
+    } else {
+        "halt".to_owned()
+    }
+}
+
+fn operand(kind: Operand, value: usize, ip_index: usize, ip: usize) -> String {
+    match kind {
+        Operand::Register if value == ip_index => ip.to_string(),
+        Operand::Register => format!("r{}", value),
+        _ => value.to_string(),
+    }
+}
+
+fn symbol(opcode: &Opcode) -> Option<&'static str> {
+    use self::Opcode::*;
+    match opcode {
+        Addr | Addi => Some("+"),
+        Mulr | Muli => Some("*"),
+        Banr | Bani => Some("&"),
+        Borr | Bori => Some("|"),
+        Gtir | Gtri | Gtrr => Some(">"),
+        Eqir | Eqri | Eqrr => Some("=="),
+        Setr | Seti | Custom(_) => None,
+    }
+}
+
+/// The value an instruction computes, e.g. `r1 * r5`.
+fn expression(instruction: &Instruction, ip_index: usize, ip: usize) -> String {
+    if let Some(value) = evaluate_constant(instruction, ip_index, ip) {
+        return value.to_string();
+    }
+
+    let (kind1, kind2) = instruction.opcode.operands();
+    let a = operand(kind1, instruction.input1, ip_index, ip);
+    let b = operand(kind2, instruction.input2, ip_index, ip);
+    match (symbol(&instruction.opcode), &instruction.opcode) {
+        (Some(symbol), _) => format!("{} {} {}", a, symbol, b),
+        (None, Opcode::Custom(custom)) => {
+            let arguments: Vec<String> = vec![(kind1, a), (kind2, b)]
+                .into_iter()
+                .filter(|&(kind, _)| kind != Operand::Ignored)
+                .map(|(_, argument)| argument)
+                .collect();
+            format!("{}({})", custom.mnemonic, arguments.join(", "))
+        }
+        (None, _) => a,
+    }
+}
+
+/// Renders one instruction as pseudo-code, e.g. `r3 = r1 * r5` or `if r5 > r2 goto L12`.
+///
+/// When the comparison before a branch overwrote one of its own inputs, the branch tests the
+/// result by name, e.g. `if r3 goto L7` after `r3 = r3 == r2`.
+pub fn statement(program: &[Instruction], ip_index: usize, ip: usize) -> String {
+    let instruction = &program[ip];
+    let len = program.len();
+
+    match flow(program, ip_index, ip) {
+        Flow::Goto(target) if target >= len => return "halt".to_owned(),
+        Flow::Goto(target) => return format!("goto {}", label(target, len)),
+        Flow::Branch {
+            condition, taken, ..
+        } => {
+            let previous = &program[ip - 1];
+            let (kind1, kind2) = previous.opcode.operands();
+            let overwritten = (kind1 == Operand::Register && previous.input1 == condition)
+                || (kind2 == Operand::Register && previous.input2 == condition);
+            let condition = if overwritten {
+                format!("r{}", condition)
+            } else {
+                expression(previous, ip_index, ip - 1)
+            };
+            return format!("if {} goto {}", condition, label(taken, len));
+        }
+        Flow::Indirect => {
+            return format!("goto {} + 1", expression(instruction, ip_index, ip));
+        }
+        Flow::Next => {}
+    }
+
+    let (kind1, kind2) = instruction.opcode.operands();
+    let output = instruction.output;
+    if let Some(symbol) =
+        symbol(&instruction.opcode).filter(|_| !is_comparison(&instruction.opcode))
+    {
+        let other = if kind1 == Operand::Register && instruction.input1 == output {
+            Some(operand(kind2, instruction.input2, ip_index, ip))
+        } else if kind2 == Operand::Register && instruction.input2 == output {
+            Some(operand(kind1, instruction.input1, ip_index, ip))
+        } else {
+            None
+        };
+        if let Some(other) = other {
+            return format!("r{} {}= {}", output, symbol, other);
+        }
+    }
+
+    format!("r{} = {}", output, expression(instruction, ip_index, ip))
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub ip: usize,
+    pub label: Option<String>,
+    pub instruction: Instruction,
+    pub statement: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            if let Some(label) = &line.label {
+                writeln!(f, "{}:", label)?;
+            }
+            let instruction = line.instruction.to_string();
+            writeln!(f, "{:4}  {:<16}  {}", line.ip, instruction, line.statement)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn disassemble<W: Word>(vm: &Vm<W>) -> Listing {
+    let program = &vm.program;
+    let targets = jump_targets(program, vm.ip_index);
+    let lines = program
+        .iter()
+        .enumerate()
+        .map(|(ip, instruction)| Line {
+            ip,
+            label: targets.get(&ip).map(|&target| label(target, program.len())),
+            instruction: instruction.clone(),
+            statement: statement(program, vm.ip_index, ip),
+        })
+        .collect();
+
+    Listing { lines }
+}
+
+/// Renders a program as source for `asm::assemble`, with jump targets labelled, absolute jumps
+/// written against those labels and each line's pseudo-code as a comment.
+pub fn assembly<W: Word>(vm: &Vm<W>) -> String {
+    let program = &vm.program;
+    let ip_index = vm.ip_index;
+    let targets = jump_targets(program, ip_index);
+    let register = |value: usize| {
+        if value == ip_index {
+            "ip".to_owned()
+        } else {
+            format!("r{}", value)
+        }
+    };
+
+    let mut source = format!("#ip {}\n", ip_index);
+    for (ip, instruction) in program.iter().enumerate() {
+        if targets.contains(&ip) {
+            source += &format!("{}:\n", label(ip, program.len()));
+        }
+
+        let (kind1, kind2) = instruction.opcode.operands();
+        let operand = |kind, value| match kind {
+            Operand::Register => register(value),
+            _ => value.to_string(),
+        };
+        let input1 = match (&instruction.opcode, flow(program, ip_index, ip)) {
+            (Opcode::Seti, Flow::Goto(target)) if target < program.len() => {
+                format!("{}-1", label(target, program.len()))
+            }
+            _ => operand(kind1, instruction.input1),
+        };
+        let code = format!(
+            "{} {} {} {}",
+            instruction.opcode,
+            input1,
+            operand(kind2, instruction.input2),
+            register(instruction.output)
+        );
+        source += &format!("    {:<20} ; {}\n", code, statement(program, ip_index, ip));
+    }
+
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY19;
+
+    #[test]
+    fn flows() {
+        let vm: Vm = DAY19.parse().unwrap();
+        let flow = |ip| flow(&vm.program, vm.ip_index(), ip);
+        assert_eq!(flow(0), Flow::Goto(17));
+        assert_eq!(flow(3), Flow::Next);
+        assert_eq!(
+            flow(5),
+            Flow::Branch {
+                condition: 3,
+                taken: 7,
+                fallthrough: 6
+            }
+        );
+        assert_eq!(flow(11), Flow::Goto(3));
+        assert_eq!(flow(16), Flow::Goto(257));
+        assert_eq!(flow(25), Flow::Indirect);
+        assert_eq!(flow(26), Flow::Goto(1));
+    }
+
+    #[test]
+    fn listing() {
+        let vm: Vm = DAY19.parse().unwrap();
+        let listing = disassemble(&vm);
+        let statements: Vec<&str> = listing
+            .lines
+            .iter()
+            .map(|line| line.statement.as_str())
+            .collect();
+        assert_eq!(
+            statements,
+            vec![
+                "goto L17",
+                "r1 = 1",
+                "r5 = 1",
+                "r3 = r1 * r5",
+                "r3 = r3 == r2",
+                "if r3 goto L7",
+                "goto L8",
+                "r0 += r1",
+                "r5 += 1",
+                "r3 = r5 > r2",
+                "if r5 > r2 goto L12",
+                "goto L3",
+                "r1 += 1",
+                "r3 = r1 > r2",
+                "if r1 > r2 goto L16",
+                "goto L2",
+                "halt",
+                "r2 += 2",
+                "r2 *= r2",
+                "r2 *= 19",
+                "r2 *= 11",
+                "r3 += 6",
+                "r3 *= 22",
+                "r3 += 8",
+                "r2 += r3",
+                "goto 25 + r0 + 1",
+                "goto L1",
+                "r3 = 27",
+                "r3 *= 28",
+                "r3 += 29",
+                "r3 *= 30",
+                "r3 *= 14",
+                "r3 *= 32",
+                "r2 += r3",
+                "r0 = 0",
+                "goto L1",
+            ]
+        );
+        assert_eq!(listing.lines[3].label.as_deref(), Some("L3"));
+        assert_eq!(listing.lines[4].label, None);
+        assert!(listing
+            .to_string()
+            .contains("L7:\n   7  addr 1 0 0        r0 += r1\n"));
+    }
+
+    #[test]
+    fn overwritten_condition() {
+        // The comparison replaces r3, so the branch must not restate it as `r3 == r2`.
+        let vm: Vm = "#ip 4\neqrr 3 2 3\naddr 3 4 4\neqrr 1 2 3\naddr 3 4 4"
+            .parse()
+            .unwrap();
+        let statement = |ip| statement(&vm.program, vm.ip_index(), ip);
+        assert_eq!(statement(0), "r3 = r3 == r2");
+        assert_eq!(statement(1), "if r3 goto L3");
+        assert_eq!(statement(3), "if r1 == r2 goto halt");
+    }
+}
@@ -0,0 +1,114 @@
+use super::Instruction;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownMnemonic,
+    MissingOperand,
+    NonNumericRegister,
+    NonNumericImmediate,
+    BadIpDirective,
+    UnexpectedToken,
+    BadDirective,
+    UndefinedSymbol,
+    DuplicateSymbol,
+    OutOfRange,
+    NoSuchRegister,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ParseErrorKind::*;
+        let description = match self {
+            UnknownMnemonic => "unknown mnemonic",
+            MissingOperand => "missing operand",
+            NonNumericRegister => "non-numeric register",
+            NonNumericImmediate => "non-numeric immediate",
+            BadIpDirective => "bad #ip directive",
+            UnexpectedToken => "unexpected token",
+            BadDirective => "bad directive",
+            UndefinedSymbol => "undefined symbol",
+            DuplicateSymbol => "duplicate symbol",
+            OutOfRange => "value out of range",
+            NoSuchRegister => "no such register",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Where and why elfcode source text failed to parse. `line` and `column` are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, column: usize, token: &str) -> ParseError {
+        ParseError {
+            kind,
+            line: 1,
+            column,
+            token: token.to_owned(),
+        }
+    }
+
+    pub fn at_line(mut self, line: usize) -> ParseError {
+        self.line = line;
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)?;
+        if !self.token.is_empty() {
+            write!(f, " `{}`", self.token)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    BadRegister(usize),
+    Overflow,
+    IpOutOfBounds,
+    UnknownOpcode,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultKind::BadRegister(index) => write!(f, "bad register {}", index),
+            FaultKind::Overflow => write!(f, "arithmetic overflow"),
+            FaultKind::IpOutOfBounds => write!(f, "ip out of bounds"),
+            FaultKind::UnknownOpcode => write!(f, "unknown opcode"),
+        }
+    }
+}
+
+/// A runtime fault, with the ip and instruction that raised it.
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub ip: usize,
+    pub instruction: Option<Instruction>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at ip {}", self.kind, self.ip)?;
+        if let Some(instruction) = &self.instruction {
+            write!(f, " `{}`", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Fault {}
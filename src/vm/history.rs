@@ -0,0 +1,198 @@
+use super::*;
+
+/// The previous values of the registers a step wrote.
+#[derive(Debug, Clone)]
+pub(super) enum Undo<W> {
+    /// A built-in instruction, which writes only its output register and the ip.
+    Step { output: usize, value: W, ip: W },
+    /// A custom instruction or an idiom, which may write any register.
+    All(Registers<W>),
+}
+
+/// An undo log of register writes, one entry per step, keeping at most `capacity` steps.
+#[derive(Debug, Clone)]
+pub(super) struct History<W> {
+    capacity: usize,
+    entries: VecDeque<Undo<W>>,
+}
+
+impl<W: Word> History<W> {
+    pub(super) fn new(capacity: usize) -> History<W> {
+        History {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub(super) fn is_recording(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Saves the output register and the ip before a built-in instruction overwrites them.
+    pub(super) fn capture_step(
+        &self,
+        registers: &Registers<W>,
+        output: usize,
+        ip_index: usize,
+    ) -> Option<Undo<W>> {
+        if !self.is_recording() {
+            return None;
+        }
+        match (registers.0.get(output), registers.0.get(ip_index)) {
+            (Some(value), Some(ip)) => Some(Undo::Step {
+                output,
+                value: value.clone(),
+                ip: ip.clone(),
+            }),
+            _ => self.capture_all(registers),
+        }
+    }
+
+    /// Saves every register, for steps that may write any of them.
+    pub(super) fn capture_all(&self, registers: &Registers<W>) -> Option<Undo<W>> {
+        if self.is_recording() {
+            Some(Undo::All(registers.clone()))
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn push(&mut self, undo: Undo<W>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(undo);
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<W: Word> Vm<W> {
+    /// Starts keeping an undo log of the last `capacity` steps, replacing any existing log. A
+    /// capacity of 0 stops recording.
+    ///
+    /// Values already emitted to `outputs` are not taken back by stepping backwards, and
+    /// registers written directly rather than by a step are not recorded.
+    pub fn record_history(&mut self, capacity: usize) {
+        self.history = History::new(capacity);
+    }
+
+    /// How many steps can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.entries.len()
+    }
+
+    /// Undoes the most recent step. Returns `false` if no recorded step is left.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.entries.pop_back() {
+            Some(undo) => {
+                match undo {
+                    Undo::Step { output, value, ip } => {
+                        self.registers.0[self.ip_index] = ip;
+                        self.registers.0[output] = value;
+                    }
+                    Undo::All(registers) => self.registers = registers,
+                }
+                self.steps -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Steps back at least once, until the instruction at `ip` is about to execute again.
+    /// Returns `false`, at the oldest recorded step, if it never was.
+    pub fn run_back_to(&mut self, ip: usize) -> bool {
+        while self.step_back() {
+            if self.current_ip().is_ok_and(|current| current == ip) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY21;
+
+    const PROGRAM: &str = r"#ip 0
+seti 0 0 2
+addi 1 1 1
+mulr 1 1 3
+addr 2 3 2
+gtri 1 4 4
+addr 0 4 0
+seti 0 0 0
+seti 7 0 5
+";
+
+    #[test]
+    fn step_back() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        vm.record_history(100);
+        let mut states = vec![vm.registers.clone()];
+        while vm.step().is_ok() {
+            states.push(vm.registers.clone());
+        }
+        assert_eq!(vm.registers.0[2], 1 + 4 + 9 + 16 + 25);
+        assert_eq!(vm.history_len(), states.len() - 1);
+
+        while let Some(state) = states.pop() {
+            assert_eq!(vm.registers, state);
+            assert_eq!(vm.steps(), states.len());
+            vm.step_back();
+        }
+        assert!(!vm.step_back());
+    }
+
+    #[test]
+    fn run_back_to() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        vm.record_history(100);
+        vm.run().unwrap();
+
+        assert!(vm.run_back_to(3));
+        assert_eq!(vm.registers.0[1..4], [5, 1 + 4 + 9 + 16, 25]);
+        assert!(vm.run_back_to(3));
+        assert_eq!(vm.registers.0[1..4], [4, 1 + 4 + 9, 16]);
+
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[2], 1 + 4 + 9 + 16 + 25);
+    }
+
+    #[test]
+    fn undo_idiom() {
+        // Starting at day21's division loop, which writes several registers in one step.
+        let mut vm: Vm = DAY21.parse().unwrap();
+        vm.registers.0[2] = 17;
+        vm.record_history(10);
+        vm.registers.0[4] = 1000;
+        let before = vm.registers.clone();
+        vm.run_with(Limits::new().max_steps(1)).unwrap();
+        assert_eq!(vm.registers.0[5], 1000 / 256);
+
+        assert!(vm.step_back());
+        assert_eq!(vm.registers, before);
+        assert_eq!(vm.steps(), 0);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        vm.record_history(4);
+        vm.run().unwrap();
+        assert_eq!(vm.history_len(), 4);
+
+        assert!(!vm.run_back_to(0));
+        assert_eq!(vm.history_len(), 0);
+        assert_eq!(*vm.ip().unwrap(), 3);
+
+        vm.record_history(0);
+        vm.run_with(Limits::new().max_steps(1)).unwrap();
+        assert_eq!(vm.history_len(), 0);
+    }
+}
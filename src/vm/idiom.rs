@@ -0,0 +1,358 @@
+use super::*;
+
+/// A loop recognised in the instruction stream that can be executed natively.
+///
+/// Register fields hold register indices; `start` is the ip of the loop's first instruction and
+/// control continues at `exit` once the idiom has been applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Idiom {
+    /// `for a in 1..=n { for b in 1..=n { if a * b == n { sum += a } } }`, as in day19.
+    DivisorSum {
+        start: usize,
+        sum: usize,
+        outer: usize,
+        inner: usize,
+        temp: usize,
+        n: usize,
+    },
+    /// `q = 0; while (q + 1) * divisor <= dividend { q += 1 }`, as in day21.
+    Division {
+        start: usize,
+        quotient: usize,
+        temp: usize,
+        dividend: usize,
+        divisor: usize,
+    },
+}
+
+impl Idiom {
+    pub fn start(&self) -> usize {
+        match *self {
+            Idiom::DivisorSum { start, .. } | Idiom::Division { start, .. } => start,
+        }
+    }
+
+    pub fn exit(&self) -> usize {
+        match self {
+            Idiom::DivisorSum { .. } => self.start() + DIVISOR_SUM.len(),
+            Idiom::Division { .. } => self.start() + DIVISION.len(),
+        }
+    }
+
+    /// Applies the effect of the whole loop, including the jump to `exit`. Returns `false` and
+    /// leaves the registers untouched if the loop would overflow or never terminate, or if the
+    /// registers hold values outside `usize`, so the caller can fall back to interpreting it.
+    pub fn apply<W: Word>(&self, registers: &mut Registers<W>, ip_index: usize) -> bool {
+        let fits = |value: usize| W::from_usize(value).is_some();
+        let mut values: Vec<usize> = match registers.0.iter().map(W::to_usize).collect() {
+            Some(values) => values,
+            None => return false,
+        };
+        match *self {
+            Idiom::DivisorSum {
+                sum,
+                outer,
+                inner,
+                temp,
+                n,
+                ..
+            } => {
+                let target = values[n];
+                if !target.checked_mul(target).is_some_and(fits) {
+                    return false;
+                }
+                match values[sum].checked_add(sum_of_divisors(target)) {
+                    Some(total) => values[sum] = total,
+                    None => return false,
+                }
+                values[outer] = target.max(1) + 1;
+                values[inner] = target.max(1) + 1;
+                values[temp] = 1;
+            }
+            Idiom::Division {
+                quotient,
+                temp,
+                dividend,
+                divisor,
+                ..
+            } => {
+                let dividend = values[dividend];
+                if divisor == 0 || !dividend.checked_add(divisor).is_some_and(fits) {
+                    return false;
+                }
+                values[quotient] = dividend / divisor;
+                values[temp] = 1;
+            }
+        }
+
+        values[ip_index] = self.exit();
+        match values.into_iter().map(W::from_usize).collect() {
+            Some(values) => {
+                registers.0 = values;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn sum_of_divisors(n: usize) -> usize {
+    let mut sum = 0;
+    let mut i = 1;
+    while i * i <= n {
+        if n.is_multiple_of(i) {
+            sum += i;
+            if i * i != n {
+                sum += n / i;
+            }
+        }
+        i += 1;
+    }
+    sum
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Arg {
+    /// A register other than the ip register, bound to a pattern variable.
+    Reg(usize),
+    Ip,
+    /// An immediate bound to a pattern variable.
+    Imm(usize),
+    Lit(usize),
+    /// An immediate equal to the pattern's start plus an offset, used for absolute jumps.
+    Rel(usize),
+    Any,
+}
+
+type Pattern = [(Opcode, Arg, Arg, Arg)];
+
+const DIVISOR_SUM_VARS: usize = 5;
+const DIVISOR_SUM: &Pattern = {
+    use self::Arg::*;
+    use self::Opcode::*;
+    let (sum, outer, inner, temp, n) = (Reg(0), Reg(1), Reg(2), Reg(3), Reg(4));
+    &[
+        (Seti, Lit(1), Any, outer),
+        (Seti, Lit(1), Any, inner),
+        (Mulr, outer, inner, temp),
+        (Eqrr, temp, n, temp),
+        (Addr, temp, Ip, Ip),
+        (Addi, Ip, Lit(1), Ip),
+        (Addr, outer, sum, sum),
+        (Addi, inner, Lit(1), inner),
+        (Gtrr, inner, n, temp),
+        (Addr, Ip, temp, Ip),
+        (Seti, Rel(1), Any, Ip),
+        (Addi, outer, Lit(1), outer),
+        (Gtrr, outer, n, temp),
+        (Addr, temp, Ip, Ip),
+        (Seti, Rel(0), Any, Ip),
+    ]
+};
+
+const DIVISION_VARS: usize = 3;
+const DIVISION: &Pattern = {
+    use self::Arg::*;
+    use self::Opcode::*;
+    let (quotient, temp, dividend) = (Reg(0), Reg(1), Reg(2));
+    &[
+        (Seti, Lit(0), Any, quotient),
+        (Addi, quotient, Lit(1), temp),
+        (Muli, temp, Imm(0), temp),
+        (Gtrr, temp, dividend, temp),
+        (Addr, temp, Ip, Ip),
+        (Addi, Ip, Lit(1), Ip),
+        (Seti, Rel(8), Any, Ip),
+        (Addi, quotient, Lit(1), quotient),
+        (Seti, Rel(0), Any, Ip),
+    ]
+};
+
+#[derive(Debug, Clone, Default)]
+struct Bindings {
+    registers: Vec<Option<usize>>,
+    immediates: Vec<Option<usize>>,
+}
+
+impl Bindings {
+    fn bind(&mut self, arg: Arg, value: usize, ip_index: usize, start: usize) -> bool {
+        match arg {
+            Arg::Reg(var) => {
+                if value == ip_index {
+                    return false;
+                }
+                match self.registers[var] {
+                    Some(register) => register == value,
+                    None if self.registers.contains(&Some(value)) => false,
+                    None => {
+                        self.registers[var] = Some(value);
+                        true
+                    }
+                }
+            }
+            Arg::Ip => value == ip_index,
+            Arg::Imm(var) => *self.immediates[var].get_or_insert(value) == value,
+            Arg::Lit(literal) => value == literal,
+            Arg::Rel(offset) => value == start + offset,
+            Arg::Any => true,
+        }
+    }
+}
+
+fn is_commutative(opcode: &Opcode) -> bool {
+    use self::Opcode::*;
+    matches!(opcode, Addr | Mulr | Banr | Borr | Eqrr)
+}
+
+fn match_at(
+    program: &[Instruction],
+    ip_index: usize,
+    start: usize,
+    pattern: &Pattern,
+    offset: usize,
+    bindings: Bindings,
+) -> Option<Bindings> {
+    let (opcode, a, b, c) = match pattern.get(offset) {
+        Some((opcode, a, b, c)) => (opcode, *a, *b, *c),
+        None => return Some(bindings),
+    };
+    let instruction = program.get(start + offset)?;
+    if instruction.opcode != *opcode {
+        return None;
+    }
+
+    let orders = if is_commutative(opcode) {
+        vec![(a, b), (b, a)]
+    } else {
+        vec![(a, b)]
+    };
+    orders.into_iter().find_map(|(a, b)| {
+        let mut bindings = bindings.clone();
+        let matched = bindings.bind(a, instruction.input1, ip_index, start)
+            && bindings.bind(b, instruction.input2, ip_index, start)
+            && bindings.bind(c, instruction.output, ip_index, start);
+        if matched {
+            match_at(program, ip_index, start, pattern, offset + 1, bindings)
+        } else {
+            None
+        }
+    })
+}
+
+fn match_pattern(
+    program: &[Instruction],
+    ip_index: usize,
+    start: usize,
+    pattern: &Pattern,
+    registers: usize,
+    immediates: usize,
+) -> Option<(Vec<usize>, Vec<usize>)> {
+    let bindings = Bindings {
+        registers: vec![None; registers],
+        immediates: vec![None; immediates],
+    };
+    let bindings = match_at(program, ip_index, start, pattern, 0, bindings)?;
+    Some((
+        bindings.registers.into_iter().map(Option::unwrap).collect(),
+        bindings
+            .immediates
+            .into_iter()
+            .map(Option::unwrap)
+            .collect(),
+    ))
+}
+
+/// Finds every idiom in a program, in ip order.
+pub fn detect(program: &[Instruction], ip_index: usize) -> Vec<Idiom> {
+    (0..program.len())
+        .filter_map(|start| {
+            if let Some((r, _)) =
+                match_pattern(program, ip_index, start, DIVISOR_SUM, DIVISOR_SUM_VARS, 0)
+            {
+                return Some(Idiom::DivisorSum {
+                    start,
+                    sum: r[0],
+                    outer: r[1],
+                    inner: r[2],
+                    temp: r[3],
+                    n: r[4],
+                });
+            }
+
+            if let Some((r, i)) =
+                match_pattern(program, ip_index, start, DIVISION, DIVISION_VARS, 1)
+            {
+                return Some(Idiom::Division {
+                    start,
+                    quotient: r[0],
+                    temp: r[1],
+                    dividend: r[2],
+                    divisor: i[0],
+                });
+            }
+
+            None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::{DAY19, DAY21};
+
+    #[test]
+    fn divisor_sum() {
+        let mut vm: Vm = DAY19.parse().unwrap();
+        let idioms = detect(&vm.program, vm.ip_index());
+        assert_eq!(
+            idioms,
+            vec![Idiom::DivisorSum {
+                start: 1,
+                sum: 0,
+                outer: 1,
+                inner: 5,
+                temp: 3,
+                n: 2,
+            }]
+        );
+
+        // Start at the loop, past the setup that picks n.
+        for &n in &[0, 1, 12, 36, 97] {
+            vm.registers = Registers(vec![5, 0, n, 0, 1, 0]);
+            let mut interpreted = vm.clone();
+            interpreted.accelerate = false;
+            interpreted.run().unwrap();
+            vm.run().unwrap();
+            assert_eq!(vm.registers, interpreted.registers);
+        }
+        assert_eq!(vm.registers.0[0], 5 + 1 + 97);
+    }
+
+    #[test]
+    fn division() {
+        let mut vm: Vm = DAY21.parse().unwrap();
+        let idioms = detect(&vm.program, vm.ip_index());
+        assert_eq!(
+            idioms,
+            vec![Idiom::Division {
+                start: 17,
+                quotient: 5,
+                temp: 1,
+                dividend: 4,
+                divisor: 256,
+            }]
+        );
+
+        // Run just the loop, which leaves at 26.
+        for &dividend in &[0, 255, 256, 65536, 1_000_000] {
+            vm.registers = Registers(vec![0, 0, 17, 0, dividend, 0]);
+            let mut interpreted = vm.clone();
+            interpreted.accelerate = false;
+            interpreted.run_with(Limits::new().break_at(26)).unwrap();
+            vm.run_with(Limits::new().break_at(26)).unwrap();
+            assert_eq!(vm.registers, interpreted.registers);
+            assert_eq!(vm.registers.0[5], dividend / 256);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use super::*;
+use strum::IntoEnumIterator;
+
+/// One execution of an instruction whose opcode is only known by number, as in day16's
+/// samples. `instruction` is `[number, input1, input2, output]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation<W = usize> {
+    pub before: Registers<W>,
+    pub instruction: [usize; 4],
+    pub after: Registers<W>,
+}
+
+impl<W: Word> Observation<W> {
+    pub fn number(&self) -> usize {
+        self.instruction[0]
+    }
+
+    /// The built-in opcodes that turn `before` into `after`.
+    pub fn candidates(&self) -> Vec<Opcode> {
+        let mask = self.mask();
+        Opcode::iter()
+            .enumerate()
+            .filter(|&(i, _)| mask & 1 << i != 0)
+            .map(|(_, opcode)| opcode)
+            .collect()
+    }
+
+    fn mask(&self) -> u16 {
+        Opcode::iter()
+            .enumerate()
+            .filter(|(_, opcode)| {
+                let instruction = Instruction::new(opcode.clone(), &self.instruction[1..4]);
+                let mut registers = self.before.clone();
+                instruction.execute(&mut registers).is_ok() && registers == self.after
+            })
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+}
+
+const ALL: u16 = u16::MAX;
+
+/// The opcodes each number may stand for, narrowed to those that appear in at least one
+/// consistent mapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidates {
+    sets: Vec<u16>,
+}
+
+impl Candidates {
+    /// The opcodes `number` may stand for.
+    pub fn get(&self, number: usize) -> Vec<Opcode> {
+        let set = self.sets.get(number).copied().unwrap_or(0);
+        Opcode::iter()
+            .enumerate()
+            .filter(|&(i, _)| set & 1 << i != 0)
+            .map(|(_, opcode)| opcode)
+            .collect()
+    }
+
+    /// The numbers whose opcode the observations do not pin down.
+    pub fn ambiguous(&self) -> Vec<usize> {
+        (0..self.sets.len())
+            .filter(|&number| self.sets[number].count_ones() > 1)
+            .collect()
+    }
+
+    /// Every consistent mapping, each indexed by number. There can be up to 16! of them, so
+    /// they are generated one at a time.
+    pub fn mappings(&self) -> Mappings<'_> {
+        Mappings {
+            sets: &self.sets,
+            opcodes: Opcode::iter().collect(),
+            choices: vec![],
+            used: 0,
+            started: false,
+        }
+    }
+}
+
+/// An iterator over the consistent mappings of some `Candidates`, in lexicographic order.
+#[derive(Debug, Clone)]
+pub struct Mappings<'a> {
+    sets: &'a [u16],
+    opcodes: Vec<Opcode>,
+    /// The opcode index chosen for each number so far.
+    choices: Vec<usize>,
+    used: u16,
+    started: bool,
+}
+
+impl Mappings<'_> {
+    fn pop(&mut self) -> Option<usize> {
+        let i = self.choices.pop()?;
+        self.used &= !(1 << i);
+        Some(i)
+    }
+}
+
+impl Iterator for Mappings<'_> {
+    type Item = Vec<Opcode>;
+
+    fn next(&mut self) -> Option<Vec<Opcode>> {
+        // Resume after the previous mapping by moving its last choice on.
+        let mut from = if self.started {
+            self.pop()? + 1
+        } else {
+            self.started = true;
+            0
+        };
+        loop {
+            let number = self.choices.len();
+            if number == self.sets.len() {
+                return Some(
+                    self.choices
+                        .iter()
+                        .map(|&i| self.opcodes[i].clone())
+                        .collect(),
+                );
+            }
+            let available = self.sets[number] & !self.used;
+            match (from..OPCODE_COUNT).find(|&i| available & 1 << i != 0) {
+                Some(i) => {
+                    self.choices.push(i);
+                    self.used |= 1 << i;
+                    from = 0;
+                }
+                None => from = self.pop()? + 1,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inference {
+    /// The opcode of each number.
+    Unique(Vec<Opcode>),
+    /// More than one mapping fits the observations.
+    Ambiguous(Candidates),
+    /// No mapping fits; these observations, by index, already contradict each other and
+    /// leaving out any one of them would resolve that.
+    Conflict(Vec<usize>),
+}
+
+/// Finds which opcode each of the numbers `0..OPCODE_COUNT` stands for, given that every
+/// number stands for a different opcode.
+pub fn infer<W: Word>(observations: &[Observation<W>]) -> Inference {
+    // A number out of range contradicts the premise on its own.
+    if let Some(i) = observations
+        .iter()
+        .position(|observation| observation.number() >= OPCODE_COUNT)
+    {
+        return Inference::Conflict(vec![i]);
+    }
+
+    let masks: Vec<u16> = observations.iter().map(Observation::mask).collect();
+    let sets_of = |included: &[usize]| {
+        let mut sets = vec![ALL; OPCODE_COUNT];
+        for &i in included {
+            sets[observations[i].number()] &= masks[i];
+        }
+        sets
+    };
+
+    let mut included: Vec<usize> = (0..observations.len()).collect();
+    let sets = sets_of(&included);
+    if !has_matching(&sets) {
+        // Drop observations that are not needed for the contradiction, leaving a set that is
+        // minimal by inclusion.
+        let mut i = 0;
+        while i < included.len() {
+            let removed = included.remove(i);
+            if has_matching(&sets_of(&included)) {
+                included.insert(i, removed);
+                i += 1;
+            }
+        }
+        return Inference::Conflict(included);
+    }
+
+    let sets: Vec<u16> = (0..OPCODE_COUNT)
+        .map(|number| {
+            (0..OPCODE_COUNT)
+                .filter(|&i| sets[number] & 1 << i != 0)
+                .filter(|&i| {
+                    let mut fixed = sets.clone();
+                    fixed[number] = 1 << i;
+                    has_matching(&fixed)
+                })
+                .fold(0, |set, i| set | 1 << i)
+        })
+        .collect();
+    if sets.iter().all(|set| set.count_ones() == 1) {
+        let opcodes: Vec<Opcode> = Opcode::iter().collect();
+        let mapping = sets
+            .iter()
+            .map(|set| opcodes[set.trailing_zeros() as usize].clone())
+            .collect();
+        Inference::Unique(mapping)
+    } else {
+        Inference::Ambiguous(Candidates { sets })
+    }
+}
+
+/// Whether every number can be given a different opcode from its set.
+fn has_matching(sets: &[u16]) -> bool {
+    fn augment(sets: &[u16], number: usize, seen: &mut u16, owner: &mut [Option<usize>]) -> bool {
+        for i in 0..OPCODE_COUNT {
+            if sets[number] & 1 << i == 0 || *seen & 1 << i != 0 {
+                continue;
+            }
+            *seen |= 1 << i;
+            if owner[i].is_none_or(|other| augment(sets, other, seen, owner)) {
+                owner[i] = Some(number);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owner = vec![None; OPCODE_COUNT];
+    (0..sets.len()).all(|number| augment(sets, number, &mut 0, &mut owner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(before: &str, instruction: [usize; 4], after: &str) -> Observation {
+        Observation {
+            before: before.parse().unwrap(),
+            instruction,
+            after: after.parse().unwrap(),
+        }
+    }
+
+    /// Observations of number `i` running the opcode with index `i`, on enough inputs to tell
+    /// it apart from every other opcode.
+    fn identity() -> Vec<Observation> {
+        let befores: Vec<Registers> =
+            vec!["3, 5, 2, 7".parse().unwrap(), "0, 9, 1, 4".parse().unwrap()];
+        let mut observations = vec![];
+        for (number, opcode) in Opcode::iter().enumerate() {
+            for before in &befores {
+                for i in 0..64 {
+                    let instruction = [number, i / 16, i / 4 % 4, i % 4];
+                    let mut after = before.clone();
+                    let executed =
+                        Instruction::new(opcode.clone(), &instruction[1..]).execute(&mut after);
+                    if executed.is_ok() {
+                        observations.push(Observation {
+                            before: before.clone(),
+                            instruction,
+                            after,
+                        });
+                    }
+                }
+            }
+        }
+        observations
+    }
+
+    #[test]
+    fn candidates() {
+        let sample = observation("3, 2, 1, 1", [9, 2, 1, 2], "3, 2, 2, 1");
+        assert_eq!(
+            sample.candidates(),
+            vec![Opcode::Addi, Opcode::Mulr, Opcode::Seti]
+        );
+    }
+
+    #[test]
+    fn unique() {
+        let mut observations = identity();
+        let inference = infer(&observations);
+        assert_eq!(inference, Inference::Unique(Opcode::iter().collect()));
+
+        // Without its own observations, a number is still pinned down by elimination.
+        observations.retain(|observation| observation.number() != 5);
+        assert_eq!(inference, infer(&observations));
+    }
+
+    #[test]
+    fn ambiguous() {
+        let mut observations = identity();
+        observations.retain(|observation| observation.number() > 1);
+        let candidates = match infer(&observations) {
+            Inference::Ambiguous(candidates) => candidates,
+            inference => panic!("{:?}", inference),
+        };
+        assert_eq!(candidates.ambiguous(), vec![0, 1]);
+        assert_eq!(candidates.get(1), vec![Opcode::Addr, Opcode::Addi]);
+        assert_eq!(candidates.get(2), vec![Opcode::Mulr]);
+
+        let mappings: Vec<Vec<Opcode>> = candidates.mappings().collect();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1][..2], [Opcode::Addi, Opcode::Addr]);
+
+        // With nothing observed every permutation fits, far too many to collect.
+        let candidates = match infer::<usize>(&[]) {
+            Inference::Ambiguous(candidates) => candidates,
+            inference => panic!("{:?}", inference),
+        };
+        assert_eq!(candidates.ambiguous().len(), OPCODE_COUNT);
+        let first: Vec<Vec<Opcode>> = candidates.mappings().take(2).collect();
+        assert_eq!(first[0], Opcode::iter().collect::<Vec<_>>());
+        assert_eq!(first[1][14..], [Opcode::Eqrr, Opcode::Eqri]);
+    }
+
+    #[test]
+    fn conflict() {
+        let mut observations = identity();
+        // Number 3 now also behaves like `addr` or `addi`, although it is `muli`.
+        let culprit = observations.len();
+        observations.push(observation("1, 1, 0, 0", [3, 0, 1, 2], "1, 1, 2, 0"));
+        observations.push(observation("1, 1, 0, 0", [0, 0, 1, 2], "1, 1, 2, 0"));
+        match infer(&observations) {
+            Inference::Conflict(indices) => {
+                assert!(indices.contains(&culprit));
+                let remaining: Vec<Observation> =
+                    indices.iter().map(|&i| observations[i].clone()).collect();
+                assert!(matches!(infer(&remaining), Inference::Conflict(_)));
+                for skip in 0..remaining.len() {
+                    let mut fewer = remaining.clone();
+                    fewer.remove(skip);
+                    assert!(!matches!(infer(&fewer), Inference::Conflict(_)));
+                }
+            }
+            inference => panic!("{:?}", inference),
+        }
+
+        let impossible = observation("1, 1, 0, 0", [4, 0, 1, 2], "9, 9, 9, 9");
+        assert_eq!(infer(&[impossible]), Inference::Conflict(vec![0]));
+
+        let mut observations = identity();
+        observations.push(observation("1, 1, 0, 0", [16, 0, 1, 2], "1, 1, 2, 0"));
+        assert_eq!(
+            infer(&observations),
+            Inference::Conflict(vec![observations.len() - 1])
+        );
+    }
+}
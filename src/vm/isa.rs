@@ -0,0 +1,287 @@
+use super::*;
+use std::sync::Arc;
+
+/// An opcode registered with an `InstructionSet`, as carried by `Opcode::Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CustomOpcode {
+    pub mnemonic: Arc<str>,
+    pub operands: (Operand, Operand),
+}
+
+/// What the VM does once an instruction has executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect<W = usize> {
+    /// Increment the ip and carry on.
+    Continue,
+    /// Stop as if the ip had left the program.
+    Halt,
+    /// Emit a value to `Vm::outputs`, then carry on.
+    Output(W),
+}
+
+/// The semantics of an opcode beyond the 16 built-in ones.
+pub trait Operation<W = usize>: Send + Sync {
+    fn mnemonic(&self) -> &str;
+
+    /// How the two inputs are interpreted, as for `Opcode::operands`.
+    fn operands(&self) -> (Operand, Operand);
+
+    fn execute(
+        &self,
+        instruction: &Instruction,
+        registers: &mut Registers<W>,
+        mode: ArithmeticMode,
+    ) -> Result<Effect<W>, FaultKind>;
+}
+
+/// An operation whose semantics are given by a closure.
+pub struct FnOperation<F> {
+    mnemonic: String,
+    operands: (Operand, Operand),
+    execute: F,
+}
+
+impl<F> FnOperation<F> {
+    pub fn new(mnemonic: &str, operands: (Operand, Operand), execute: F) -> FnOperation<F> {
+        FnOperation {
+            mnemonic: mnemonic.to_owned(),
+            operands,
+            execute,
+        }
+    }
+}
+
+impl<W, F> Operation<W> for FnOperation<F>
+where
+    F: Fn(&Instruction, &mut Registers<W>, ArithmeticMode) -> Result<Effect<W>, FaultKind>
+        + Send
+        + Sync,
+{
+    fn mnemonic(&self) -> &str {
+        &self.mnemonic
+    }
+
+    fn operands(&self) -> (Operand, Operand) {
+        self.operands
+    }
+
+    fn execute(
+        &self,
+        instruction: &Instruction,
+        registers: &mut Registers<W>,
+        mode: ArithmeticMode,
+    ) -> Result<Effect<W>, FaultKind> {
+        (self.execute)(instruction, registers, mode)
+    }
+}
+
+/// `out a _ _` emits the value of register `a`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Out;
+
+impl<W: Word> Operation<W> for Out {
+    fn mnemonic(&self) -> &str {
+        "out"
+    }
+
+    fn operands(&self) -> (Operand, Operand) {
+        (Operand::Register, Operand::Ignored)
+    }
+
+    fn execute(
+        &self,
+        instruction: &Instruction,
+        registers: &mut Registers<W>,
+        _: ArithmeticMode,
+    ) -> Result<Effect<W>, FaultKind> {
+        registers.read(instruction.input1).map(Effect::Output)
+    }
+}
+
+/// The opcodes a program may use: the 16 built-in ones, which are always present, plus any
+/// registered operations. The default set is what day16, day19 and day21 run on.
+pub struct InstructionSet<W = usize> {
+    operations: Vec<Arc<dyn Operation<W>>>,
+}
+
+impl<W> Clone for InstructionSet<W> {
+    fn clone(&self) -> Self {
+        InstructionSet {
+            operations: self.operations.clone(),
+        }
+    }
+}
+
+impl<W> Default for InstructionSet<W> {
+    fn default() -> Self {
+        InstructionSet { operations: vec![] }
+    }
+}
+
+impl<W> fmt::Debug for InstructionSet<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.operations.iter().map(|operation| operation.mnemonic()))
+            .finish()
+    }
+}
+
+impl<W: Word> InstructionSet<W> {
+    pub fn new() -> InstructionSet<W> {
+        Default::default()
+    }
+
+    /// Registers an operation. Panics if its mnemonic is already taken.
+    pub fn with(mut self, operation: impl Operation<W> + 'static) -> InstructionSet<W> {
+        assert!(
+            self.opcode(operation.mnemonic()).is_none(),
+            "mnemonic `{}` is already defined",
+            operation.mnemonic()
+        );
+        self.operations.push(Arc::new(operation));
+        self
+    }
+
+    pub fn opcode(&self, mnemonic: &str) -> Option<Opcode> {
+        mnemonic.parse().ok().or_else(|| {
+            self.operation(mnemonic).map(|operation| {
+                Opcode::Custom(CustomOpcode {
+                    mnemonic: mnemonic.into(),
+                    operands: operation.operands(),
+                })
+            })
+        })
+    }
+
+    fn operation(&self, mnemonic: &str) -> Option<&dyn Operation<W>> {
+        self.operations
+            .iter()
+            .find(|operation| operation.mnemonic() == mnemonic)
+            .map(|operation| operation.as_ref())
+    }
+
+    pub fn execute(
+        &self,
+        instruction: &Instruction,
+        registers: &mut Registers<W>,
+        mode: ArithmeticMode,
+    ) -> Result<Effect<W>, FaultKind> {
+        match &instruction.opcode {
+            Opcode::Custom(custom) => self
+                .operation(&custom.mnemonic)
+                .ok_or(FaultKind::UnknownOpcode)?
+                .execute(instruction, registers, mode),
+            _ => instruction
+                .execute_with(registers, mode)
+                .map(|_| Effect::Continue),
+        }
+    }
+
+    pub fn parse_instruction(&self, s: &str) -> Result<Instruction, ParseError> {
+        parse_instruction(s, |mnemonic| self.opcode(mnemonic))
+    }
+
+    /// Parses a program that may use this set's operations, validating registers as
+    /// `Vm::parse_with` does.
+    pub fn parse_program(&self, s: &str, config: Config) -> Result<Vm<W>, ParseError> {
+        Vm::parse(s, config, true, false, self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    fn extended() -> InstructionSet {
+        use self::Operand::*;
+        InstructionSet::new()
+            .with(FnOperation::new(
+                "divr",
+                (Register, Register),
+                |i: &Instruction, r: &mut Registers, _| {
+                    let divisor = r.read(i.input2)?;
+                    let quotient = r.read(i.input1)?.checked_div(divisor);
+                    r.write(i.output, quotient.ok_or(FaultKind::Overflow)?)?;
+                    Ok(Effect::Continue)
+                },
+            ))
+            .with(FnOperation::new(
+                "modi",
+                (Register, Immediate),
+                |i: &Instruction, r: &mut Registers, _| {
+                    let remainder = r.read(i.input1)?.checked_rem(i.input2);
+                    r.write(i.output, remainder.ok_or(FaultKind::Overflow)?)?;
+                    Ok(Effect::Continue)
+                },
+            ))
+            .with(FnOperation::new(
+                "nop",
+                (Ignored, Ignored),
+                |_: &Instruction, _: &mut Registers, _| Ok(Effect::Continue),
+            ))
+            .with(FnOperation::new(
+                "halt",
+                (Ignored, Ignored),
+                |_: &Instruction, _: &mut Registers, _| Ok(Effect::Halt),
+            ))
+    }
+
+    const PROGRAM: &str = r"#ip 5
+seti 47 0 1
+seti 5 0 2
+divr 1 2 3
+modi 1 5 4
+nop 0 0 0
+halt 0 0 0
+seti 99 0 3
+";
+
+    #[test]
+    fn custom_opcodes() {
+        let mut vm = extended().parse_program(PROGRAM, Config::DAY19).unwrap();
+        assert_eq!(vm.program[2].to_string(), "divr 1 2 3");
+        assert_eq!(
+            vm.program[2].opcode.operands(),
+            (Operand::Register, Operand::Register)
+        );
+
+        vm.run().unwrap();
+        assert_eq!(vm.registers.0[1..5], [47, 5, 9, 2]);
+        assert_eq!(vm.registers.0[5], 7);
+
+        let listing = disasm::disassemble(&vm);
+        assert_eq!(listing.lines[2].statement, "r3 = divr(r1, r2)");
+        assert_eq!(listing.lines[3].statement, "r4 = modi(r1, 5)");
+
+        vm.registers = Registers::default();
+        vm.registers.0[2] = 0;
+        vm.program[1].input1 = 0;
+        let fault = vm.run().unwrap_err();
+        assert_eq!((fault.kind, fault.ip), (FaultKind::Overflow, 2));
+    }
+
+    #[test]
+    fn default_set() {
+        let error = PROGRAM.parse::<Vm>().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnknownMnemonic);
+        assert_eq!((error.line, error.token.as_str()), (4, "divr"));
+
+        let mut vm = extended().parse_program(PROGRAM, Config::DAY19).unwrap();
+        vm.instruction_set = InstructionSet::new();
+        assert_eq!(vm.run().unwrap_err().kind, FaultKind::UnknownOpcode);
+
+        assert_eq!(Opcode::iter().count(), OPCODE_COUNT);
+        assert!("custom".parse::<Opcode>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "mnemonic `addr` is already defined")]
+    fn duplicate_mnemonic() {
+        InstructionSet::<usize>::new().with(FnOperation::new(
+            "addr",
+            (Operand::Register, Operand::Register),
+            |_: &Instruction, _: &mut Registers, _| Ok(Effect::Continue),
+        ));
+    }
+}
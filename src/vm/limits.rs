@@ -0,0 +1,83 @@
+use super::trace::Observer;
+use super::{Registers, Word};
+use std::time::Duration;
+
+pub type Predicate<'a, W = usize> = Box<dyn FnMut(&Registers<W>) -> bool + 'a>;
+
+/// How often `Vm::run_with` consults the clock when a time budget is set.
+pub(super) const CLOCK_INTERVAL: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halted,
+    StepLimit,
+    TimeLimit,
+    Breakpoint(usize),
+    /// A value was emitted; take it with `Vm::take_output`.
+    Output,
+    /// Index of the predicate, in the order they were added with `Limits::until`.
+    Predicate(usize),
+}
+
+/// Conditions under which `Vm::run_with` stops before the program halts.
+///
+/// Breakpoints and predicates are checked after each step, so resuming from a breakpoint
+/// always makes progress. Observers see every instruction, so idioms are not accelerated while
+/// any are attached.
+pub struct Limits<'a, W = usize> {
+    pub(super) max_steps: Option<usize>,
+    pub(super) time_budget: Option<Duration>,
+    pub(super) breakpoints: Vec<usize>,
+    pub(super) predicates: Vec<Predicate<'a, W>>,
+    pub(super) observers: Vec<&'a mut dyn Observer<W>>,
+    pub(super) stop_on_output: bool,
+}
+
+impl<'a, W: Word> Default for Limits<'a, W> {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            time_budget: None,
+            breakpoints: vec![],
+            predicates: vec![],
+            observers: vec![],
+            stop_on_output: false,
+        }
+    }
+}
+
+impl<'a, W: Word> Limits<'a, W> {
+    pub fn new() -> Limits<'a, W> {
+        Default::default()
+    }
+
+    pub fn max_steps(mut self, steps: usize) -> Limits<'a, W> {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    pub fn time_budget(mut self, budget: Duration) -> Limits<'a, W> {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    pub fn break_at(mut self, ip: usize) -> Limits<'a, W> {
+        self.breakpoints.push(ip);
+        self
+    }
+
+    pub fn until(mut self, predicate: impl FnMut(&Registers<W>) -> bool + 'a) -> Limits<'a, W> {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn until_output(mut self) -> Limits<'a, W> {
+        self.stop_on_output = true;
+        self
+    }
+
+    pub fn observe(mut self, observer: &'a mut dyn Observer<W>) -> Limits<'a, W> {
+        self.observers.push(observer);
+        self
+    }
+}
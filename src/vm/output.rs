@@ -0,0 +1,86 @@
+use super::*;
+
+/// Emits the value of `register` each time the instruction at `ip` is about to execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tap {
+    pub ip: usize,
+    pub register: usize,
+}
+
+/// Values emitted by `out` instructions and taps, produced by running the VM on demand.
+///
+/// Iteration ends when the program halts or faults; `finish` tells the two apart.
+pub struct Outputs<'a, W: Word = usize> {
+    vm: &'a mut Vm<W>,
+    fault: Option<Fault>,
+}
+
+impl<'a, W: Word> Outputs<'a, W> {
+    pub(super) fn new(vm: &'a mut Vm<W>) -> Outputs<'a, W> {
+        Outputs { vm, fault: None }
+    }
+
+    pub fn finish(self) -> Result<(), Fault> {
+        match self.fault {
+            Some(fault) => Err(fault),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, W: Word> Iterator for Outputs<'a, W> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        if self.fault.is_some() {
+            return None;
+        }
+        if let Some(value) = self.vm.take_output() {
+            return Some(value);
+        }
+
+        match self.vm.run_with(Limits::new().until_output()) {
+            Ok(StopReason::Output) => self.vm.take_output(),
+            Ok(_) => None,
+            Err(fault) => {
+                self.fault = Some(fault);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_instruction() {
+        let program =
+            "#ip 0\nseti 0 0 3\naddi 1 1 1\nout 1 0 0\ngtri 1 2 2\naddr 0 2 0\nseti 0 0 0";
+        let mut vm = isa::InstructionSet::new()
+            .with(isa::Out)
+            .parse_program(program, Config::DAY19)
+            .unwrap();
+        let values: Vec<usize> = vm.outputs().collect();
+        assert_eq!(values, vec![1, 2, 3]);
+        assert!(vm.outputs().finish().is_ok());
+    }
+
+    #[test]
+    fn taps() {
+        let program = "#ip 0\nseti 0 0 3\naddi 1 3 1\nmulr 1 1 2\nseti 0 0 0";
+        let mut vm: Vm = program.parse().unwrap();
+        vm.taps.push(Tap { ip: 3, register: 2 });
+        let values: Vec<usize> = vm.outputs().take_while(|&v| v < 100).collect();
+        assert_eq!(values, vec![9, 36, 81]);
+
+        vm.registers.0[1] = 7;
+        assert_eq!(vm.outputs().next(), Some(100));
+
+        vm.registers.0[1] = usize::MAX / 2;
+        let mut outputs = vm.outputs();
+        assert_eq!(outputs.next(), None);
+        assert_eq!(outputs.finish().unwrap_err().kind, FaultKind::Overflow);
+    }
+}
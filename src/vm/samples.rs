@@ -0,0 +1,218 @@
+use super::infer::Observation;
+use super::*;
+use std::fmt::Write;
+use strum::IntoEnumIterator;
+
+/// A small xorshift generator, so puzzles are reproducible from their seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Spread the seed with a splitmix64 round so that nearby seeds diverge and 0 is valid.
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// A random numbering of the built-in opcodes, indexed by number.
+pub fn shuffled(seed: u64) -> Vec<Opcode> {
+    let mut opcodes: Vec<Opcode> = Opcode::iter().collect();
+    Rng::new(seed).shuffle(&mut opcodes);
+    opcodes
+}
+
+/// A synthetic day16 puzzle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    /// The puzzle text: samples, then the test program.
+    pub input: String,
+    pub numbering: Vec<Opcode>,
+    /// Numbers that no sample uses, in ascending order.
+    pub unobserved: Vec<usize>,
+    /// The registers after running the test program on the day16 machine.
+    pub registers: Registers,
+}
+
+/// Generates day16-format puzzles for a given numbering.
+///
+/// Every observed number gets samples until they pin its opcode down, so the puzzle has a
+/// unique solution unless two or more numbers are left unobserved, in which case exactly
+/// those numbers are ambiguous.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    numbering: Vec<Opcode>,
+    rng: Rng,
+    unobserved: usize,
+    extra_samples: usize,
+    program_len: usize,
+}
+
+impl Generator {
+    pub fn new(numbering: Vec<Opcode>, seed: u64) -> Generator {
+        assert_eq!(
+            numbering.len(),
+            OPCODE_COUNT,
+            "numbering must cover every opcode"
+        );
+        Generator {
+            numbering,
+            rng: Rng::new(seed),
+            unobserved: 0,
+            extra_samples: 0,
+            program_len: 20,
+        }
+    }
+
+    /// Leaves `count` randomly chosen numbers without samples.
+    pub fn unobserved(mut self, count: usize) -> Generator {
+        self.unobserved = count.min(OPCODE_COUNT);
+        self
+    }
+
+    /// Adds up to `count` more samples for each observed number beyond those needed to pin it
+    /// down.
+    pub fn extra_samples(mut self, count: usize) -> Generator {
+        self.extra_samples = count;
+        self
+    }
+
+    pub fn program_len(mut self, len: usize) -> Generator {
+        self.program_len = len;
+        self
+    }
+
+    pub fn generate(&mut self) -> Puzzle {
+        let mut numbers: Vec<usize> = (0..OPCODE_COUNT).collect();
+        self.rng.shuffle(&mut numbers);
+        let mut unobserved = numbers.split_off(OPCODE_COUNT - self.unobserved);
+        unobserved.sort_unstable();
+
+        let mut samples = vec![];
+        for &number in &numbers {
+            let mut candidates = OPCODE_COUNT;
+            let mut extra = self.rng.below(self.extra_samples + 1);
+            let mut remaining: Vec<Opcode> = Opcode::iter().collect();
+            while candidates > 1 || extra > 0 {
+                if candidates == 1 {
+                    extra -= 1;
+                }
+                let sample = self.sample(number);
+                let explained = sample.candidates();
+                remaining.retain(|opcode| explained.contains(opcode));
+                candidates = remaining.len();
+                samples.push(sample);
+            }
+        }
+        self.rng.shuffle(&mut samples);
+
+        let mut input = String::new();
+        for sample in &samples {
+            writeln!(input, "Before: [{}]", join(&sample.before.0, ", ")).unwrap();
+            writeln!(input, "{}", join(&sample.instruction, " ")).unwrap();
+            writeln!(input, "After:  [{}]", join(&sample.after.0, ", ")).unwrap();
+            writeln!(input).unwrap();
+        }
+        writeln!(input).unwrap();
+        writeln!(input).unwrap();
+
+        let mut registers = Registers::new(Config::DAY16);
+        let mut written = 0;
+        while written < self.program_len {
+            let code = self.code();
+            let number = self.rng.below(OPCODE_COUNT);
+            let instruction = Instruction::new(self.numbering[number].clone(), &code);
+            // Skip anything that would overflow, so the program always runs to completion.
+            let mut next = registers.clone();
+            if instruction.execute(&mut next).is_ok() {
+                registers = next;
+                writeln!(input, "{} {}", number, join(&code, " ")).unwrap();
+                written += 1;
+            }
+        }
+
+        Puzzle {
+            input,
+            numbering: self.numbering.clone(),
+            unobserved,
+            registers,
+        }
+    }
+
+    /// Operands in `0..4`, which are valid as both registers and immediates.
+    fn code(&mut self) -> [usize; 3] {
+        [self.rng.below(4), self.rng.below(4), self.rng.below(4)]
+    }
+
+    fn sample(&mut self, number: usize) -> Observation {
+        let code = self.code();
+        let before = Registers(
+            (0..Config::DAY16.registers)
+                .map(|_| self.rng.below(8))
+                .collect(),
+        );
+        let mut after = before.clone();
+        Instruction::new(self.numbering[number].clone(), &code)
+            .execute(&mut after)
+            .unwrap();
+        Observation {
+            before,
+            instruction: [number, code[0], code[1], code[2]],
+            after,
+        }
+    }
+}
+
+fn join(values: &[usize], separator: &str) -> String {
+    let values: Vec<String> = values.iter().map(usize::to_string).collect();
+    values.join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+
+        let generate = |seed| {
+            Generator::new(shuffled(seed), seed)
+                .unobserved(3)
+                .generate()
+        };
+        let puzzle = generate(1);
+        assert_eq!(puzzle, generate(1));
+        assert_eq!(puzzle.unobserved.len(), 3);
+        assert!(puzzle.input.starts_with("Before: ["));
+        assert_eq!(
+            puzzle
+                .input
+                .lines()
+                .rev()
+                .take_while(|l| !l.is_empty())
+                .count(),
+            20
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use super::*;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::thread;
+
+/// How one run of a search ended.
+#[derive(Debug, Clone)]
+pub struct Run<W = usize> {
+    pub initial: Registers<W>,
+    /// Whether the program halted within the budget. Runs that fault have not halted.
+    pub halted: bool,
+    pub fault: Option<Fault>,
+    /// Instructions executed, counting each accelerated idiom as one.
+    pub steps: usize,
+    pub registers: Registers<W>,
+}
+
+/// What to sort the runs of a search by, in ascending order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Steps,
+    /// The final value of a register.
+    Register(usize),
+}
+
+impl Metric {
+    fn compare<W: Word>(self, a: &Run<W>, b: &Run<W>) -> Ordering {
+        match self {
+            Metric::Steps => a.steps.cmp(&b.steps),
+            Metric::Register(index) => a.registers.0.get(index).cmp(&b.registers.0.get(index)),
+        }
+    }
+}
+
+/// An initial register file too short to hold the program's ip, with its index among the
+/// initials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadInitial {
+    pub index: usize,
+    pub kind: FaultKind,
+}
+
+impl fmt::Display for BadInitial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "initial registers {}: {}", self.index, self.kind)
+    }
+}
+
+impl Error for BadInitial {}
+
+/// Runs copies of a VM from many initial register values on several threads.
+///
+/// Step counts treat each accelerated idiom as one step; clear `Vm::accelerate` first to count
+/// every instruction.
+pub struct Search<'a, W = usize> {
+    vm: &'a Vm<W>,
+    budget: usize,
+    threads: usize,
+}
+
+impl<'a, W: Word + Send + Sync> Search<'a, W> {
+    pub fn new(vm: &'a Vm<W>) -> Search<'a, W> {
+        Search {
+            vm,
+            budget: usize::MAX,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Stops each run after `steps` instructions.
+    pub fn budget(mut self, steps: usize) -> Search<'a, W> {
+        self.budget = steps;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Search<'a, W> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Runs the VM once from each of `initials`, returning the halted runs sorted by `metric`
+    /// followed by the rest in the same order. Fails before running anything if any of
+    /// `initials` has no register for the ip.
+    pub fn run(
+        &self,
+        initials: Vec<Registers<W>>,
+        metric: Metric,
+    ) -> Result<Vec<Run<W>>, BadInitial> {
+        for (index, initial) in initials.iter().enumerate() {
+            initial
+                .read(self.vm.ip_index())
+                .map_err(|kind| BadInitial { index, kind })?;
+        }
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(initials.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(initials.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, atomic::Ordering::Relaxed);
+                    match initials.get(index) {
+                        Some(initial) => {
+                            let run = self.run_one(initial.clone());
+                            results.lock().unwrap().push((index, run));
+                        }
+                        None => break,
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|&(index, _)| index);
+        let mut runs: Vec<Run<W>> = results.into_iter().map(|(_, run)| run).collect();
+        runs.sort_by(|a, b| match (a.halted, b.halted) {
+            (true, true) => metric.compare(a, b),
+            _ => b.halted.cmp(&a.halted),
+        });
+        Ok(runs)
+    }
+
+    fn run_one(&self, initial: Registers<W>) -> Run<W> {
+        let mut vm = self.vm.clone();
+        vm.registers = initial.clone();
+        let steps = vm.steps();
+        let result = vm.run_with(Limits::new().max_steps(self.budget));
+        Run {
+            initial,
+            halted: matches!(result, Ok(StopReason::Halted)),
+            fault: result.err(),
+            steps: vm.steps() - steps,
+            registers: vm.registers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY21_SAMPLE;
+
+    #[test]
+    fn search() {
+        let vm: Vm = DAY21_SAMPLE.parse().unwrap();
+        let initials: Vec<Registers> = [99, 17, 1, 24, 16, 200]
+            .iter()
+            .map(|&r0| Registers(vec![r0, 0, 0, 0, 0, 0]))
+            .collect();
+        let runs = Search::new(&vm)
+            .budget(1000)
+            .threads(3)
+            .run(initials, Metric::Steps)
+            .unwrap();
+
+        let order: Vec<usize> = runs.iter().map(|run| run.initial.0[0]).collect();
+        assert_eq!(order, [1, 24, 16, 17, 99, 200]);
+        assert!(runs[..4]
+            .iter()
+            .all(|run| run.halted && run.fault.is_none()));
+        assert_eq!(runs[0].steps, 4);
+        assert_eq!(runs[3].registers.0[1], 17);
+        assert!(!runs[4].halted);
+        assert_eq!(runs[4].steps, 1000);
+
+        let initials: Vec<Registers> = [99, 24, 16]
+            .iter()
+            .map(|&r0| Registers(vec![r0, 0, 0, 0, 0, 0]))
+            .collect();
+        let runs = Search::new(&vm)
+            .budget(1000)
+            .run(initials, Metric::Register(1))
+            .unwrap();
+        let order: Vec<usize> = runs.iter().map(|run| run.registers.0[1]).collect();
+        assert_eq!(order[..2], [16, 24]);
+
+        let initials = vec![Registers(vec![0; 6]), Registers(vec![0; 5])];
+        let error = Search::new(&vm).run(initials, Metric::Steps).unwrap_err();
+        assert_eq!(
+            error,
+            BadInitial {
+                index: 1,
+                kind: FaultKind::BadRegister(5)
+            }
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use super::*;
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+/// The first line of every snapshot. The number changes if the format ever does.
+const HEADER: &str = "#snapshot 1";
+
+impl<W: Word> Vm<W> {
+    /// Serialises the whole machine state as text: a header of `#` directives followed by the
+    /// program in the usual `#ip` format, so a snapshot is also a readable listing.
+    ///
+    /// The instruction set itself is not saved; restore programs using custom opcodes with
+    /// `restore_with`.
+    pub fn snapshot(&self) -> String {
+        let registers: Vec<String> = self.registers.0.iter().map(|v| v.to_string()).collect();
+        let mut s = format!("{}\n", HEADER);
+        s += &format!("#registers {}\n", registers.join(", "));
+        s += &format!("#steps {}\n", self.steps);
+        s += &format!("#arithmetic {}\n", self.arithmetic);
+        s += &format!("#accelerate {}\n", self.accelerate);
+        for tap in &self.taps {
+            s += &format!("#tap {} {}\n", tap.ip, tap.register);
+        }
+        for value in &self.output {
+            s += &format!("#output {}\n", value);
+        }
+        s += &format!("#ip {}\n", self.ip_index);
+        for instruction in &self.program {
+            s += &format!("{}\n", instruction);
+        }
+        s
+    }
+
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(self.snapshot().as_bytes())
+    }
+
+    pub fn restore(s: &str) -> Result<Vm<W>, ParseError> {
+        Vm::restore_with(s, &isa::InstructionSet::new())
+    }
+
+    pub fn restore_with(
+        s: &str,
+        instruction_set: &isa::InstructionSet<W>,
+    ) -> Result<Vm<W>, ParseError> {
+        let mut lines = s.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line.trim_end() == HEADER => {}
+            line => {
+                let token = line.map_or("", |(_, line)| line.trim());
+                return Err(ParseError::new(ParseErrorKind::BadDirective, 1, token));
+            }
+        }
+
+        let mut registers = None;
+        let mut steps = 0;
+        let mut arithmetic = ArithmeticMode::default();
+        let mut accelerate = true;
+        let mut taps = vec![];
+        let mut output = VecDeque::new();
+        let mut program = None;
+        for (i, line) in lines {
+            let at_line = |e: ParseError| e.at_line(i + 1);
+            let tokens: Vec<(usize, &str)> = tokens(line).collect();
+            let (column, directive) = match tokens.first() {
+                Some(&(_, "#ip")) => {
+                    program = Some(i);
+                    break;
+                }
+                Some(&first) => first,
+                None => continue,
+            };
+
+            let arity = match directive {
+                "#registers" => tokens.len(),
+                "#tap" => 3,
+                _ => 2,
+            };
+            if let Some(&(column, token)) = tokens.get(arity) {
+                let e = ParseError::new(ParseErrorKind::UnexpectedToken, column, token);
+                return Err(at_line(e));
+            }
+
+            match directive {
+                "#registers" => {
+                    let start = tokens.get(1).map_or(line.len(), |&(column, _)| column - 1);
+                    let parsed: Registers<W> = line[start..].parse().map_err(|e: ParseError| {
+                        ParseError::new(e.kind, e.column + start, &e.token).at_line(i + 1)
+                    })?;
+                    registers = Some(parsed);
+                }
+                "#steps" => steps = argument(&tokens, 1, line).map_err(at_line)?,
+                "#arithmetic" => arithmetic = argument(&tokens, 1, line).map_err(at_line)?,
+                "#accelerate" => accelerate = argument(&tokens, 1, line).map_err(at_line)?,
+                "#tap" => taps.push(Tap {
+                    ip: argument(&tokens, 1, line).map_err(at_line)?,
+                    register: argument(&tokens, 2, line).map_err(at_line)?,
+                }),
+                "#output" => output.push_back(argument(&tokens, 1, line).map_err(at_line)?),
+                _ => {
+                    let e = ParseError::new(ParseErrorKind::BadDirective, column, directive);
+                    return Err(at_line(e));
+                }
+            }
+        }
+
+        let registers = registers
+            .ok_or_else(|| ParseError::new(ParseErrorKind::BadDirective, 1, "#registers"))?;
+        let program_line = program.unwrap_or_else(|| s.lines().count());
+        let rest: Vec<&str> = s.lines().skip(program_line).collect();
+        let config = Config {
+            registers: registers.0.len(),
+        };
+        let mut vm = Vm::parse(
+            &rest.join("\n"),
+            config,
+            false,
+            false,
+            instruction_set.clone(),
+        )
+        .map_err(|e| {
+            let line = e.line + program_line;
+            e.at_line(line)
+        })?;
+
+        vm.registers = registers;
+        vm.steps = steps;
+        vm.arithmetic = arithmetic;
+        vm.accelerate = accelerate;
+        vm.taps = taps;
+        vm.output = output;
+        Ok(vm)
+    }
+
+    pub fn load(mut reader: impl Read) -> Result<Vm<W>, Box<dyn Error>> {
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        Ok(Vm::restore(&s)?)
+    }
+}
+
+fn argument<T: FromStr>(
+    tokens: &[(usize, &str)],
+    index: usize,
+    line: &str,
+) -> Result<T, ParseError> {
+    let &(column, token) = tokens.get(index).ok_or_else(|| {
+        ParseError::new(
+            ParseErrorKind::MissingOperand,
+            line.trim_end().len() + 1,
+            "",
+        )
+    })?;
+    token
+        .parse()
+        .map_err(|_| ParseError::new(ParseErrorKind::BadDirective, column, token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY21;
+
+    #[test]
+    fn snapshot_and_resume() {
+        let mut vm: Vm = DAY21.parse().unwrap();
+        vm.taps.push(Tap {
+            ip: 28,
+            register: 3,
+        });
+        let mut expected = vm.clone();
+        let expected: Vec<usize> = expected.outputs().take(3).collect();
+
+        let first = vm.outputs().next().unwrap();
+        vm.run_with(Limits::new().max_steps(500)).unwrap();
+        let snapshot = vm.snapshot();
+        assert!(snapshot.starts_with("#snapshot 1\n#registers "));
+        assert!(snapshot.contains(&format!("#steps {}\n", vm.steps())));
+        assert!(snapshot.contains("#tap 28 3\n"));
+        assert!(snapshot.ends_with("eqrr 3 0 5\naddr 5 2 2\nseti 5 4 2\n"));
+
+        let mut saved = vec![];
+        vm.save(&mut saved).unwrap();
+        let mut restored: Vm = Vm::load(saved.as_slice()).unwrap();
+        assert_eq!(restored.registers, vm.registers);
+        assert_eq!(restored.program, vm.program);
+        assert_eq!(restored.steps(), vm.steps());
+        assert_eq!(restored.snapshot(), snapshot);
+
+        let mut resumed = vec![first];
+        resumed.extend(restored.outputs().take(2));
+        assert_eq!(resumed, expected);
+    }
+
+    #[test]
+    fn restore_errors() {
+        let error = Vm::<usize>::restore("#ip 0\nseti 1 0 0").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::BadDirective);
+        assert_eq!(error.token, "#ip 0");
+
+        let error = Vm::<usize>::restore("#snapshot 1\n#ip 0\nseti 1 0 0").unwrap_err();
+        assert_eq!(error.token, "#registers");
+
+        let snapshot = "#snapshot 1\n#registers 0, 0\n#arithmetic fast\n#ip 0\n";
+        let error = Vm::<usize>::restore(snapshot).unwrap_err();
+        assert_eq!((error.line, error.column), (3, 13));
+
+        let snapshot = "#snapshot 1\n#registers 0, x\n#ip 0\n";
+        let error = Vm::<usize>::restore(snapshot).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NonNumericRegister);
+        assert_eq!((error.line, error.column), (2, 15));
+
+        let snapshot = "#snapshot 1\n#registers 0, 0\n#ip 0\nseti 1 0 0\nsetx 1 0 0\n";
+        let error = Vm::<usize>::restore(snapshot).unwrap_err();
+        assert_eq!(
+            (error.line, error.kind),
+            (5, ParseErrorKind::UnknownMnemonic)
+        );
+
+        let snapshot = "#snapshot 1\n#registers 0, 0\n#ip 2\n";
+        let error = Vm::<usize>::restore(snapshot).unwrap_err();
+        assert_eq!(
+            (error.line, error.kind),
+            (3, ParseErrorKind::BadIpDirective)
+        );
+    }
+}
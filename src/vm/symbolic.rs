@@ -0,0 +1,429 @@
+use super::*;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Mul,
+    And,
+    Or,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    fn evaluate(self, a: usize, b: usize) -> Option<usize> {
+        match self {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Mul => a.checked_mul(b),
+            BinaryOp::And => Some(a & b),
+            BinaryOp::Or => Some(a | b),
+            BinaryOp::Gt => Some((a > b) as usize),
+            BinaryOp::Eq => Some((a == b) as usize),
+        }
+    }
+
+    fn is_commutative(self) -> bool {
+        self != BinaryOp::Gt
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Mul => "*",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+            BinaryOp::Gt => ">",
+            BinaryOp::Eq => "==",
+        }
+    }
+}
+
+/// A register value in terms of the registers' values at the start of execution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+    Const(usize),
+    /// The initial value of a register.
+    Initial(usize),
+    Binary(BinaryOp, Rc<Expr>, Rc<Expr>),
+}
+
+impl Expr {
+    /// Builds `a op b`, folding constants and dropping identities such as `x + 0`.
+    /// Constant operands of commutative operations are moved to the right.
+    pub fn binary(op: BinaryOp, a: Expr, b: Expr) -> Expr {
+        use self::BinaryOp::*;
+        use self::Expr::*;
+        let (a, b) = match (&a, &b) {
+            (Const(_), Const(_)) | (_, Const(_)) => (a, b),
+            (Const(_), _) if op.is_commutative() => (b, a),
+            _ => (a, b),
+        };
+        match (op, &a, &b) {
+            (_, &Const(x), &Const(y)) => {
+                if let Some(value) = op.evaluate(x, y) {
+                    return Const(value);
+                }
+            }
+            (Add, _, Const(0)) | (Mul, _, Const(1)) | (Or, _, Const(0)) => return a,
+            (Mul, _, Const(0)) | (And, _, Const(0)) => return Const(0),
+            (Eq, _, _) if a == b => return Const(1),
+            (Gt, _, _) if a == b => return Const(0),
+            (Add, Binary(Add, x, y), &Const(c)) | (Mul, Binary(Mul, x, y), &Const(c)) => {
+                if let Const(inner) = **y {
+                    if let Some(value) = op.evaluate(inner, c) {
+                        return Expr::binary(op, (**x).clone(), Const(value));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Binary(op, Rc::new(a), Rc::new(b))
+    }
+
+    pub fn as_const(&self) -> Option<usize> {
+        match *self {
+            Expr::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression can only be 0 or 1.
+    pub fn is_boolean(&self) -> bool {
+        match self {
+            Expr::Const(value) => *value <= 1,
+            Expr::Binary(op, _, _) => *op == BinaryOp::Gt || *op == BinaryOp::Eq,
+            Expr::Initial(_) => false,
+        }
+    }
+
+    /// Replaces every occurrence of `from` with `to`, simplifying as it goes.
+    pub fn substitute(&self, from: &Expr, to: &Expr) -> Expr {
+        if self == from {
+            return to.clone();
+        }
+        match self {
+            Expr::Binary(op, a, b) => {
+                Expr::binary(*op, a.substitute(from, to), b.substitute(from, to))
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Initial(register) => write!(f, "r{}", register),
+            Expr::Binary(op, a, b) => {
+                let operand = |f: &mut fmt::Formatter, expr: &Expr| match expr {
+                    Expr::Binary(inner, _, _) if inner != op || !op.is_commutative() => {
+                        write!(f, "({})", expr)
+                    }
+                    _ => write!(f, "{}", expr),
+                };
+                operand(f, a)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, b)
+            }
+        }
+    }
+}
+
+/// That `expr` held `value` on the way to a state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assumption {
+    pub expr: Expr,
+    pub value: usize,
+}
+
+impl fmt::Display for Assumption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.expr {
+            Expr::Binary(..) => write!(f, "({}) == {}", self.expr, self.value),
+            expr => write!(f, "{} == {}", expr, self.value),
+        }
+    }
+}
+
+/// One path through a program: the ip it has reached, each register's value and the
+/// assumptions that lead here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub ip: usize,
+    pub registers: Vec<Expr>,
+    pub assumptions: Vec<Assumption>,
+}
+
+impl State {
+    /// A state at ip 0 where every register holds its unknown initial value.
+    pub fn new(config: Config) -> State {
+        State {
+            ip: 0,
+            registers: (0..config.registers).map(Expr::Initial).collect(),
+            assumptions: vec![],
+        }
+    }
+
+    /// A state at ip 0 with an unknown for every register of `vm`, including an ip register
+    /// the program does not name.
+    pub fn for_vm<W: Word>(vm: &Vm<W>) -> State {
+        State::new(Config {
+            registers: vm.registers.0.len(),
+        })
+    }
+
+    /// Fixes the initial value of `register`.
+    pub fn with(mut self, register: usize, value: usize) -> State {
+        self.substitute(&Expr::Initial(register), value);
+        self
+    }
+
+    /// Records that `expr == value` and substitutes it everywhere. Returns `false` if that
+    /// is impossible or contradicts an earlier assumption.
+    fn assume(&mut self, expr: Expr, value: usize) -> bool {
+        let (expr, value) = match solve(expr, value) {
+            Some(solved) => solved,
+            None => return false,
+        };
+        if expr.is_boolean() && value > 1 || !self.substitute(&expr, value) {
+            return false;
+        }
+        if expr.as_const().is_none() {
+            self.assumptions.push(Assumption { expr, value });
+        }
+        true
+    }
+
+    fn substitute(&mut self, expr: &Expr, value: usize) -> bool {
+        let to = Expr::Const(value);
+        for register in &mut self.registers {
+            *register = register.substitute(expr, &to);
+        }
+        for assumption in &mut self.assumptions {
+            assumption.expr = assumption.expr.substitute(expr, &to);
+            if assumption
+                .expr
+                .as_const()
+                .is_some_and(|v| v != assumption.value)
+            {
+                return false;
+            }
+        }
+        expr.as_const().is_none_or(|v| v == value)
+    }
+
+    /// Executes the instruction at `ip`, returning every state it may lead to.
+    fn step(mut self, program: &[Instruction], ip_index: usize) -> Option<Vec<State>> {
+        use self::BinaryOp::*;
+        use self::Opcode::*;
+        let instruction = &program[self.ip];
+        // A state sized for the visible registers has no slot for an unbound ip.
+        if let Some(register) = self.registers.get_mut(ip_index) {
+            *register = Expr::Const(self.ip);
+        }
+
+        let (kind1, kind2) = instruction.opcode.operands();
+        let operand = |kind, value: usize| match kind {
+            Operand::Register => self.registers.get(value).cloned(),
+            _ => Some(Expr::Const(value)),
+        };
+        let a = operand(kind1, instruction.input1)?;
+        let b = operand(kind2, instruction.input2)?;
+        let value = match instruction.opcode {
+            Addr | Addi => Expr::binary(Add, a, b),
+            Mulr | Muli => Expr::binary(Mul, a, b),
+            Banr | Bani => Expr::binary(And, a, b),
+            Borr | Bori => Expr::binary(Or, a, b),
+            Setr | Seti => a,
+            Gtir | Gtri | Gtrr => Expr::binary(Gt, a, b),
+            Eqir | Eqri | Eqrr => Expr::binary(Eq, a, b),
+            Custom(_) => return None,
+        };
+        *self.registers.get_mut(instruction.output)? = value;
+
+        let ip = self
+            .registers
+            .get(ip_index)
+            .cloned()
+            .unwrap_or(Expr::Const(self.ip));
+        if let Some(ip) = ip.as_const() {
+            self.ip = ip.checked_add(1)?;
+            return Some(vec![self]);
+        }
+        // Fork on every value of the ip register that keeps control inside the program.
+        let successors = (0..program.len().saturating_sub(1))
+            .filter_map(|target| {
+                let mut state = self.clone();
+                state.ip = target + 1;
+                if state.assume(ip.clone(), target) {
+                    Some(state)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Some(successors)
+    }
+}
+
+/// Rewrites `expr == value` so a single unknown is on the left where that is easy,
+/// e.g. `r0 + 25 == 27` becomes `r0 == 2`. Returns `None` if there is no solution.
+fn solve(expr: Expr, value: usize) -> Option<(Expr, usize)> {
+    if let Expr::Binary(BinaryOp::Add, a, b) = &expr {
+        if let Some(c) = b.as_const() {
+            return solve((**a).clone(), value.checked_sub(c)?);
+        }
+    }
+    Some((expr, value))
+}
+
+/// The states in which symbolic execution reached its target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub reached: Vec<State>,
+    /// Some paths were abandoned, because the step budget ran out or they used a custom
+    /// opcode, so `reached` may be missing states.
+    pub incomplete: bool,
+}
+
+impl Paths {
+    /// The value of each register if it is the same on every path, e.g. a constant that a
+    /// setup block computes whatever branches it takes.
+    pub fn common(&self) -> Vec<Option<Expr>> {
+        let mut states = self.reached.iter();
+        let first = match states.next() {
+            Some(first) => first,
+            None => return vec![],
+        };
+        let mut common: Vec<Option<Expr>> = first.registers.iter().cloned().map(Some).collect();
+        for state in states {
+            for (value, register) in common.iter_mut().zip(&state.registers) {
+                if value.as_ref() != Some(register) {
+                    *value = None;
+                }
+            }
+        }
+        common
+    }
+}
+
+/// Executes a program symbolically from `start` until each path is about to execute the
+/// instruction at `target`, treating the initial registers as unknowns.
+///
+/// A jump to an unknown ip forks a path for every target inside the program, assuming the
+/// value that leads there; paths that leave the program are dropped. Loops with known
+/// conditions are unrolled, so `max_steps` bounds the total number of instructions executed.
+pub fn execute<W: Word>(vm: &Vm<W>, start: State, target: usize, max_steps: usize) -> Paths {
+    let mut paths = Paths {
+        reached: vec![],
+        incomplete: false,
+    };
+    let mut pending = vec![start];
+    let mut steps = 0;
+    while let Some(state) = pending.pop() {
+        if state.ip == target {
+            paths.reached.push(state);
+            continue;
+        }
+        if state.ip >= vm.program.len() {
+            continue;
+        }
+        if steps == max_steps {
+            paths.incomplete = true;
+            break;
+        }
+        steps += 1;
+        match state.step(&vm.program, vm.ip_index) {
+            Some(successors) => pending.extend(successors.into_iter().rev()),
+            None => paths.incomplete = true,
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY19;
+
+    #[test]
+    fn straight_line() {
+        let vm: Vm = "#ip 5\naddi 0 3 1\nmulr 1 2 2\nmuli 2 4 2\ngtri 1 7 3\nbori 3 0 4"
+            .parse()
+            .unwrap();
+        let paths = execute(&vm, State::new(Config::DAY19), 5, 100);
+        assert!(!paths.incomplete);
+        let state = &paths.reached[0];
+        assert_eq!(state.registers[1].to_string(), "r0 + 3");
+        assert_eq!(state.registers[2].to_string(), "(r0 + 3) * r2 * 4");
+        assert_eq!(state.registers[3].to_string(), "(r0 + 3) > 7");
+        assert_eq!(state.registers[4], state.registers[3]);
+
+        let paths = execute(&vm, State::new(Config::DAY19).with(0, 5), 5, 100);
+        assert_eq!(paths.reached[0].registers[3], Expr::Const(1));
+    }
+
+    #[test]
+    fn day19_setup() {
+        let vm: Vm = DAY19.parse().unwrap();
+        let mut start = State::new(Config::DAY19);
+        for register in 1..6 {
+            start = start.with(register, 0);
+        }
+
+        let paths = execute(&vm, start.clone(), 1, 1000);
+        assert!(!paths.incomplete);
+        let part1 = paths
+            .reached
+            .iter()
+            .find(|state| state.assumptions[0].to_string() == "r0 == 0")
+            .unwrap();
+        assert_eq!(part1.registers[2], Expr::Const(976));
+        let part2 = paths
+            .reached
+            .iter()
+            .find(|state| state.assumptions[0].to_string() == "r0 == 1")
+            .unwrap();
+        assert_eq!(part2.registers[2], Expr::Const(10551376));
+
+        let paths = execute(&vm, start.with(0, 1), 1, 1000);
+        assert_eq!(paths.reached.len(), 1);
+        assert_eq!(paths.common()[2], Some(Expr::Const(10551376)));
+        assert_eq!(paths.common()[0], Some(Expr::Const(0)));
+    }
+
+    #[test]
+    fn branches() {
+        let vm: Vm = "#ip 5\ngtri 0 9 1\naddr 1 5 5\nseti 1 0 2\nseti 2 0 3\nseti 2 0 4"
+            .parse()
+            .unwrap();
+        let paths = execute(&vm, State::new(Config::DAY19).with(2, 7), 4, 100);
+        assert_eq!(paths.reached.len(), 2);
+        assert_eq!(paths.reached[0].assumptions[0].to_string(), "(r0 > 9) == 0");
+        assert_eq!(paths.reached[0].registers[2], Expr::Const(1));
+        assert_eq!(paths.reached[1].registers[2], Expr::Const(7));
+
+        let common = paths.common();
+        assert_eq!(common[3], Some(Expr::Const(2)));
+        assert_eq!(common[2], None);
+
+        let paths = execute(&vm, State::new(Config::DAY19), 4, 2);
+        assert!(paths.incomplete);
+    }
+
+    #[test]
+    fn unbound_ip() {
+        // A decoded day16 program, whose ip lives in a fifth register of its own.
+        let vm: Vm =
+            Vm::parse_unbound("seti 3 0 1\naddr 1 0 2\nmulr 2 2 3", Config::DAY16).unwrap();
+        for start in [State::new(Config::DAY16), State::for_vm(&vm)] {
+            let paths = execute(&vm, start, 3, 100);
+            assert!(!paths.incomplete);
+            let registers = &paths.reached[0].registers;
+            assert_eq!(registers[2].to_string(), "r0 + 3");
+            assert_eq!(registers[3].to_string(), "(r0 + 3) * (r0 + 3)");
+        }
+    }
+}
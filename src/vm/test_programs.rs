@@ -0,0 +1,104 @@
+// Programs shared by the tests of the VM and the days that run on it.
+
+/// The day19 input. Instructions 1 to 15 sum the divisors of r2, which the setup from 17 on
+/// makes 976, or 10551376 when r0 starts at 1.
+pub const DAY19: &str = r"#ip 4
+addi 4 16 4
+seti 1 8 1
+seti 1 3 5
+mulr 1 5 3
+eqrr 3 2 3
+addr 3 4 4
+addi 4 1 4
+addr 1 0 0
+addi 5 1 5
+gtrr 5 2 3
+addr 4 3 4
+seti 2 2 4
+addi 1 1 1
+gtrr 1 2 3
+addr 3 4 4
+seti 1 4 4
+mulr 4 4 4
+addi 2 2 2
+mulr 2 2 2
+mulr 4 2 2
+muli 2 11 2
+addi 3 6 3
+mulr 3 4 3
+addi 3 8 3
+addr 2 3 2
+addr 4 0 4
+seti 0 1 4
+setr 4 4 3
+mulr 3 4 3
+addr 4 3 3
+mulr 4 3 3
+muli 3 14 3
+mulr 3 4 3
+addr 2 3 2
+seti 0 4 0
+seti 0 7 4
+";
+
+/// The day19 sample, which leaves 7 in r0.
+pub const DAY19_SAMPLE: &str = r"#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5
+";
+
+/// The day21 input. Instructions 17 to 25 divide r4 by 256, and the program halts at 28 when
+/// r3 equals r0.
+pub const DAY21: &str = r"#ip 2
+seti 123 0 3
+bani 3 456 3
+eqri 3 72 3
+addr 3 2 2
+seti 0 0 2
+seti 0 0 3
+bori 3 65536 4
+seti 10649702 3 3
+bani 4 255 5
+addr 3 5 3
+bani 3 16777215 3
+muli 3 65899 3
+bani 3 16777215 3
+gtir 256 4 5
+addr 5 2 2
+addi 2 1 2
+seti 27 7 2
+seti 0 6 5
+addi 5 1 1
+muli 1 256 1
+gtrr 1 4 1
+addr 1 2 2
+addi 2 1 2
+seti 25 9 2
+addi 5 1 5
+seti 17 9 2
+setr 5 7 4
+seti 7 1 2
+eqrr 3 0 5
+addr 5 2 2
+seti 5 4 2
+";
+
+/// A day21 in miniature, which halts when r0 equals r1 at ip 1. After its first value, r1 runs
+/// through 16..32 in a period of 16.
+pub const DAY21_SAMPLE: &str = r"#ip 5
+seti 1 0 1
+eqrr 1 0 2
+addr 2 5 5
+seti 4 0 5
+seti 99 0 5
+muli 1 5 1
+addi 1 3 1
+bani 1 15 1
+bori 1 16 1
+seti 0 0 5
+";
@@ -0,0 +1,266 @@
+use super::cfg::Cfg;
+use super::*;
+use std::cmp::Reverse;
+use std::io::{self, Write};
+
+/// Receives every instruction executed by `Vm::step_observed` or `Vm::run_with`.
+pub trait Observer<W = usize> {
+    fn observe(
+        &mut self,
+        ip: usize,
+        instruction: &Instruction,
+        before: &Registers<W>,
+        after: &Registers<W>,
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// `ip instruction | before -> after`, one step per line.
+    Text,
+    /// One JSON object per line with `ip`, `instruction`, `before` and `after` fields.
+    JsonLines,
+}
+
+/// Writes each step to `writer`. The first write error stops tracing and is returned by
+/// `finish`.
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W, format: TraceFormat) -> Tracer<W> {
+        Tracer {
+            writer,
+            format,
+            error: None,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+
+    fn write<T: Word>(
+        &mut self,
+        ip: usize,
+        instruction: &Instruction,
+        before: &Registers<T>,
+        after: &Registers<T>,
+    ) -> io::Result<()> {
+        let join = |registers: &Registers<T>, separator| {
+            let values: Vec<String> = registers.0.iter().map(|v| v.to_string()).collect();
+            values.join(separator)
+        };
+
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.writer,
+                "{} {} | {} -> {}",
+                ip,
+                instruction,
+                join(before, " "),
+                join(after, " ")
+            ),
+            TraceFormat::JsonLines => writeln!(
+                self.writer,
+                r#"{{"ip":{},"instruction":"{}","before":[{}],"after":[{}]}}"#,
+                ip,
+                instruction,
+                join(before, ","),
+                join(after, ",")
+            ),
+        }
+    }
+}
+
+impl<W: Write, T: Word> Observer<T> for Tracer<W> {
+    fn observe(
+        &mut self,
+        ip: usize,
+        instruction: &Instruction,
+        before: &Registers<T>,
+        after: &Registers<T>,
+    ) {
+        if self.error.is_none() {
+            self.error = self.write(ip, instruction, before, after).err();
+        }
+    }
+}
+
+/// Counts how many times each instruction is executed.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    counts: Vec<usize>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Default::default()
+    }
+
+    pub fn count(&self, ip: usize) -> usize {
+        self.counts.get(ip).copied().unwrap_or(0)
+    }
+
+    pub fn report<W: Word>(&self, vm: &Vm<W>) -> Report {
+        let total = self.counts.iter().sum();
+        let statement = |ip| disasm::statement(&vm.program, vm.ip_index, ip);
+
+        let mut instructions: Vec<InstructionProfile> = (0..vm.program.len())
+            .filter(|&ip| self.count(ip) > 0)
+            .map(|ip| InstructionProfile {
+                ip,
+                count: self.count(ip),
+                statement: statement(ip),
+            })
+            .collect();
+        instructions.sort_by_key(|profile| (Reverse(profile.count), profile.ip));
+
+        let mut blocks: Vec<BlockProfile> = Cfg::new(vm)
+            .blocks
+            .iter()
+            .map(|block| BlockProfile {
+                start: block.start,
+                end: block.end,
+                entries: self.count(block.start),
+                executed: (block.start..=block.end).map(|ip| self.count(ip)).sum(),
+            })
+            .filter(|profile| profile.executed > 0)
+            .collect();
+        blocks.sort_by_key(|profile| (Reverse(profile.executed), profile.start));
+
+        Report {
+            total,
+            instructions,
+            blocks,
+        }
+    }
+}
+
+impl<W: Word> Observer<W> for Profiler {
+    fn observe(&mut self, ip: usize, _: &Instruction, _: &Registers<W>, _: &Registers<W>) {
+        if ip >= self.counts.len() {
+            self.counts.resize(ip + 1, 0);
+        }
+        self.counts[ip] += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionProfile {
+    pub ip: usize,
+    pub count: usize,
+    pub statement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProfile {
+    pub start: usize,
+    pub end: usize,
+    pub entries: usize,
+    pub executed: usize,
+}
+
+/// Executed instructions and basic blocks, hottest first.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub total: usize,
+    pub instructions: Vec<InstructionProfile>,
+    pub blocks: Vec<BlockProfile>,
+}
+
+const REPORT_ROWS: usize = 10;
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count| 100.0 * count as f64 / self.total.max(1) as f64;
+
+        writeln!(f, "{} instructions executed", self.total)?;
+        writeln!(f, "hot instructions:")?;
+        for profile in self.instructions.iter().take(REPORT_ROWS) {
+            writeln!(
+                f,
+                "{:>6}  {:>12}  {:5.1}%  {}",
+                profile.ip,
+                profile.count,
+                percent(profile.count),
+                profile.statement
+            )?;
+        }
+
+        writeln!(f, "hot blocks:")?;
+        for profile in self.blocks.iter().take(REPORT_ROWS) {
+            writeln!(
+                f,
+                "{:>6}  {:>12}  {:5.1}%  {} entries",
+                format!("{}-{}", profile.start, profile.end),
+                profile.executed,
+                percent(profile.executed),
+                profile.entries
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r"#ip 0
+seti 0 0 2
+addi 1 1 1
+gtri 1 2 3
+addr 0 3 0
+seti 0 0 0
+seti 7 0 4
+";
+
+    #[test]
+    fn trace() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        let mut tracer = Tracer::new(vec![], TraceFormat::Text);
+        vm.step_observed(&mut tracer).unwrap();
+        vm.step_observed(&mut tracer).unwrap();
+        let mut json = Tracer::new(vec![], TraceFormat::JsonLines);
+        vm.run_with(Limits::new().max_steps(1).observe(&mut json))
+            .unwrap();
+
+        let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "0 seti 0 0 2 | 0 0 0 0 0 0 -> 1 0 0 0 0 0\n\
+             1 addi 1 1 1 | 1 0 0 0 0 0 -> 2 1 0 0 0 0\n"
+        );
+        let json = String::from_utf8(json.finish().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            "{\"ip\":2,\"instruction\":\"gtri 1 2 3\",\
+             \"before\":[2,1,0,0,0,0],\"after\":[3,1,0,0,0,0]}\n"
+        );
+    }
+
+    #[test]
+    fn profile() {
+        let mut vm: Vm = PROGRAM.parse().unwrap();
+        let mut profiler = Profiler::new();
+        vm.run_with(Limits::new().observe(&mut profiler)).unwrap();
+
+        assert_eq!(profiler.count(1), 3);
+        assert_eq!(profiler.count(4), 2);
+        let report = profiler.report(&vm);
+        assert_eq!(report.total, 13);
+        assert_eq!(report.instructions[0].ip, 1);
+        assert_eq!(report.instructions[0].statement, "r1 += 1");
+        assert_eq!((report.blocks[0].start, report.blocks[0].end), (1, 3));
+        assert_eq!(report.blocks[0].entries, 3);
+        assert!(report.to_string().starts_with("13 instructions executed\n"));
+    }
+}
@@ -0,0 +1,151 @@
+use super::*;
+use std::fmt::Write;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// The primitive integer words, which the transpiled source can name and which have inherent
+/// `checked_*`, `wrapping_*` and `saturating_*` methods. It is sealed, since other words would
+/// produce source that does not compile.
+pub trait Primitive: Word + sealed::Sealed {
+    const NAME: &'static str;
+}
+
+macro_rules! impl_primitive {
+    ($($t:ty),*) => {
+        $(
+            impl sealed::Sealed for $t {}
+
+            impl Primitive for $t {
+                const NAME: &'static str = stringify!($t);
+            }
+        )*
+    };
+}
+
+impl_primitive!(u32, u64, u128, usize, i64);
+
+/// Translates a program into the source of a standalone Rust function named `name`, which runs
+/// it on a fixed register array until the ip leaves the program.
+///
+/// The function returns `Err(ip)` if the instruction at `ip` overflows in checked arithmetic.
+/// The name of `W` becomes the register type. Returns `None` if the program uses custom
+/// opcodes, names a register outside the VM's register file or has an immediate that does not
+/// fit in `W`.
+pub fn transpile<W: Primitive>(vm: &Vm<W>, name: &str) -> Option<String> {
+    let count = vm.registers.0.len();
+    let ip_register = format!("registers[{}]", vm.ip_index);
+    let mode = vm.arithmetic;
+    let ty = W::NAME;
+
+    let mut source = String::new();
+    writeln!(source, "/// Transpiled from elfcode.").unwrap();
+    writeln!(
+        source,
+        "pub fn {}(registers: &mut [{}; {}]) -> Result<(), usize> {{",
+        name, ty, count
+    )
+    .unwrap();
+    writeln!(source, "    loop {{").unwrap();
+    writeln!(source, "        match {} {{", ip_register).unwrap();
+    for (ip, instruction) in vm.program.iter().enumerate() {
+        let operand = |kind, value: usize| match kind {
+            Operand::Register if value < count => Some(format!("registers[{}]", value)),
+            Operand::Register => None,
+            _ => W::from_usize(value).map(|_| value.to_string()),
+        };
+        let (kind1, kind2) = instruction.opcode.operands();
+        let a = operand(kind1, instruction.input1)?;
+        let b = operand(kind2, instruction.input2);
+        let output = operand(Operand::Register, instruction.output)?;
+        let value = expression(&instruction.opcode, mode, ip, ty, a, b)?;
+
+        writeln!(source, "            {} => {{", ip).unwrap();
+        writeln!(source, "                // {}", instruction).unwrap();
+        writeln!(source, "                {} = {};", output, value).unwrap();
+        let next = arithmetic("add", mode, ip, &ip_register, "1");
+        writeln!(source, "                {} = {};", ip_register, next).unwrap();
+        writeln!(source, "            }}").unwrap();
+    }
+    writeln!(source, "            _ => return Ok(()),").unwrap();
+    writeln!(source, "        }}").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+    Some(source)
+}
+
+fn arithmetic(op: &str, mode: ArithmeticMode, ip: usize, a: &str, b: &str) -> String {
+    match mode {
+        ArithmeticMode::Checked => format!("{}.checked_{}({}).ok_or({}usize)?", a, op, b, ip),
+        ArithmeticMode::Wrapping => format!("{}.wrapping_{}({})", a, op, b),
+        ArithmeticMode::Saturating => format!("{}.saturating_{}({})", a, op, b),
+    }
+}
+
+fn expression(
+    opcode: &Opcode,
+    mode: ArithmeticMode,
+    ip: usize,
+    ty: &str,
+    a: String,
+    b: Option<String>,
+) -> Option<String> {
+    use self::Opcode::*;
+    let expression = match opcode {
+        Addr | Addi => arithmetic("add", mode, ip, &a, &b?),
+        Mulr | Muli => arithmetic("mul", mode, ip, &a, &b?),
+        Banr | Bani => format!("{} & {}", a, b?),
+        Borr | Bori => format!("{} | {}", a, b?),
+        Setr | Seti => a,
+        Gtir | Gtri | Gtrr => format!("({} > {}) as {}", a, b?, ty),
+        Eqir | Eqri | Eqrr => format!("({} == {}) as {}", a, b?, ty),
+        Custom(_) => return None,
+    };
+    Some(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::test_programs::DAY19_SAMPLE;
+
+    // Generated by `transpile` from `DAY19_SAMPLE`; `transpiled_source` checks it is current.
+    include!("transpile/day19_sample.rs");
+
+    #[test]
+    fn transpiled_source() {
+        let vm: Vm = DAY19_SAMPLE.parse().unwrap();
+        assert_eq!(
+            transpile(&vm, "day19_sample").unwrap(),
+            include_str!("transpile/day19_sample.rs")
+        );
+    }
+
+    #[test]
+    fn same_registers() {
+        let mut vm: Vm = DAY19_SAMPLE.parse().unwrap();
+        vm.run().unwrap();
+
+        let mut registers = [0; 6];
+        day19_sample(&mut registers).unwrap();
+        assert_eq!(registers[..], vm.registers.0[..]);
+        assert_eq!(registers[0], 7);
+    }
+
+    #[test]
+    fn unsupported() {
+        let vm: Vm = "#ip 0\nseti 1 0 9".parse().unwrap();
+        assert_eq!(transpile(&vm, "f"), None);
+
+        let vm: Vm<u32> = "#ip 0\nseti 5000000000 0 1".parse().unwrap();
+        assert_eq!(transpile(&vm, "f"), None);
+
+        let mut vm: Vm<u64> = "#ip 2\nmuli 1 3 1\ngtrr 1 0 3".parse().unwrap();
+        vm.arithmetic = ArithmeticMode::Wrapping;
+        let source = transpile(&vm, "f").unwrap();
+        assert!(source.contains("pub fn f(registers: &mut [u64; 6])"));
+        assert!(source.contains("registers[1] = registers[1].wrapping_mul(3);"));
+        assert!(source.contains("registers[3] = (registers[1] > registers[0]) as u64;"));
+    }
+}
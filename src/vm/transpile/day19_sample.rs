@@ -0,0 +1,43 @@
+/// Transpiled from elfcode.
+pub fn day19_sample(registers: &mut [usize; 6]) -> Result<(), usize> {
+    loop {
+        match registers[0] {
+            0 => {
+                // seti 5 0 1
+                registers[1] = 5;
+                registers[0] = registers[0].checked_add(1).ok_or(0usize)?;
+            }
+            1 => {
+                // seti 6 0 2
+                registers[2] = 6;
+                registers[0] = registers[0].checked_add(1).ok_or(1usize)?;
+            }
+            2 => {
+                // addi 0 1 0
+                registers[0] = registers[0].checked_add(1).ok_or(2usize)?;
+                registers[0] = registers[0].checked_add(1).ok_or(2usize)?;
+            }
+            3 => {
+                // addr 1 2 3
+                registers[3] = registers[1].checked_add(registers[2]).ok_or(3usize)?;
+                registers[0] = registers[0].checked_add(1).ok_or(3usize)?;
+            }
+            4 => {
+                // setr 1 0 0
+                registers[0] = registers[1];
+                registers[0] = registers[0].checked_add(1).ok_or(4usize)?;
+            }
+            5 => {
+                // seti 8 0 4
+                registers[4] = 8;
+                registers[0] = registers[0].checked_add(1).ok_or(5usize)?;
+            }
+            6 => {
+                // seti 9 0 5
+                registers[5] = 9;
+                registers[0] = registers[0].checked_add(1).ok_or(6usize)?;
+            }
+            _ => return Ok(()),
+        }
+    }
+}
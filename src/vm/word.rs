@@ -0,0 +1,84 @@
+use num_traits::{FromPrimitive, One, ToPrimitive, Zero};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// A value held in a register.
+///
+/// Only `Clone` is required, so arbitrary-precision integers can be used as well as the
+/// primitive types. Types without a fixed width can implement the wrapping and saturating
+/// operations as exact arithmetic.
+pub trait Word:
+    Clone + Debug + Display + FromStr + Ord + Hash + Zero + One + FromPrimitive + ToPrimitive + 'static
+{
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+    fn bitand(&self, other: &Self) -> Self;
+    fn bitor(&self, other: &Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                fn wrapping_add(&self, other: &Self) -> Self {
+                    <$t>::wrapping_add(*self, *other)
+                }
+
+                fn saturating_add(&self, other: &Self) -> Self {
+                    <$t>::saturating_add(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_mul(*self, *other)
+                }
+
+                fn wrapping_mul(&self, other: &Self) -> Self {
+                    <$t>::wrapping_mul(*self, *other)
+                }
+
+                fn saturating_mul(&self, other: &Self) -> Self {
+                    <$t>::saturating_mul(*self, *other)
+                }
+
+                fn bitand(&self, other: &Self) -> Self {
+                    *self & *other
+                }
+
+                fn bitor(&self, other: &Self) -> Self {
+                    *self | *other
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(u32, u64, u128, usize, i64);
+
+/// The shape of a register file. The word type is chosen separately, as the type parameter of
+/// `Registers` and `Vm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub registers: usize,
+}
+
+impl Config {
+    /// The four-register machine used by day16's samples.
+    pub const DAY16: Config = Config { registers: 4 };
+    /// The six-register machine used by day19 and day21.
+    pub const DAY19: Config = Config { registers: 6 };
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::DAY19
+    }
+}